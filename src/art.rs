@@ -1,103 +1,123 @@
-use core::iter::Peekable;
+use core::borrow::Borrow;
+use core::cmp::{self, Ordering};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::{FromIterator, Peekable};
+use core::marker::PhantomData;
+use core::ops::{Bound, Index, RangeBounds};
+use core::mem;
 use core::ptr;
+use std::io::{self, Read, Write};
 
+use crate::dump;
+use crate::iter::*;
+use crate::key::*;
 use crate::map::*;
 use crate::node::*;
+use crate::snapshot;
+use crate::stats::{self, Stats};
+use crate::validate::{self, ValidationError};
 
 /// Adaptive radix tree.
 ///
-/// IMPORTANT: you should not change this type.
-#[derive(Debug)]
-pub struct Art<V> {
+/// The keys are of type `K`, stored as their encodings given by `ArtKey`.
+pub struct Art<V, K: ?Sized = str> {
     root: NodeBox<V>,
+    _marker: PhantomData<Box<K>>,
 }
 
 #[derive(Debug)]
 struct Cursor<'a, V> {
     depth: usize,
+    /// The ancestors of `parent` from the root, each with the index of its child on the path.
+    ancestors: Vec<(*mut NodeBox<V>, u8)>,
     parent: Option<&'a mut NodeBox<V>>,
     child: &'a mut NodeBox<V>,
     index: u8,
     length: u8,
 }
 
+impl<'a, V> Cursor<'a, V> {
+    /// Applies `f` to the leaf counts of the ancestors of `child`.
+    fn update_counts<F: Fn(&mut usize)>(&mut self, f: F) {
+        for (node, _) in &self.ancestors {
+            f(unsafe { &mut **node }.deref_mut().unwrap().0.count_mut());
+        }
+        if let Some(parent) = &mut self.parent {
+            f(parent.deref_mut().unwrap().0.count_mut());
+        }
+    }
+}
+
 /// Entry API for Art.
 ///
 /// See https://doc.rust-lang.org/std/collections/hash_map/enum.Entry.html for more details of the
 /// entry API.
 #[derive(Debug)]
-pub struct Entry<'a, V, I: Iterator<Item = u8> + DoubleEndedIterator> {
+pub enum Entry<'a, V, K: ?Sized = str> {
+    /// An occupied entry.
+    Occupied(OccupiedEntry<'a, V, K>),
+    /// A vacant entry.
+    Vacant(VacantEntry<'a, V, K>),
+}
+
+/// An occupied entry of Art, which is a part of the `Entry` enum.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, V, K: ?Sized = str> {
+    /// The cursor whose child is the leaf of the entry.
     cursor: Cursor<'a, V>,
-    key: Peekable<I>,
+    /// The encoded key.
+    key: Vec<u8>,
+    _marker: PhantomData<&'a K>,
 }
 
-impl<'a, V, I: 'a + Iterator<Item = u8> + DoubleEndedIterator> Entry<'a, V, I> {
-    /// Inserts the generated value if the entry is vacant.
-    ///
-    /// Returns `Ok(v)` if inserted, where `v` is a mutable reference to the inserted value;
-    /// `Err((v, f))` if not inserted, where `v` is a mutable reference to the existing value and
-    /// `f` is the given value generator.
-    #[inline]
-    pub fn or_insert_with<F>(mut self, f: F) -> Result<&'a mut V, (&'a mut V, F)> //Entry, 
+/// A vacant entry of Art, which is a part of the `Entry` enum.
+#[derive(Debug)]
+pub struct VacantEntry<'a, V, K: ?Sized = str> {
+    /// The cursor pointing to where the key diverges from the tree.
+    cursor: Cursor<'a, V>,
+    /// The encoded key, of which the first `cursor.depth` bytes are already matched.
+    key: Vec<u8>,
+    _marker: PhantomData<&'a K>,
+}
+
+/// Decodes an encoded key given by `key::encode_key()`.
+fn decode_key<K: ?Sized + ArtKey>(key: &[u8]) -> K::Owned {
+    K::decode(&unescape(&key[0..key.len() - 1]))
+}
+
+impl<'a, V, K: ?Sized + ArtKey> Entry<'a, V, K> {
+    /// Ensures a value is in the entry by inserting the default if empty, and returns a mutable
+    /// reference to the value in the entry.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default function if empty,
+    /// and returns a mutable reference to the value in the entry.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
     where
         F: FnOnce() -> V,
     {
-        if self.key.peek() == None {
-            let val = self.cursor.child.deref_mut().unwrap().1.right().unwrap();
-            return Err((val, f));
-        }
-        let (header, b) = self.cursor.child.deref_mut().unwrap();
-        let f_key = *self.key.peek().unwrap();
-        // call new path on key, creating new leaf node
-        let (node_box, node_body_v) = NodeBox::new_path(self.key, f);
-        //path expantion is needed
-        if self.cursor.length != (header.length() as u8) {
-            // create header with common part
-            let new_header = NodeHeader::new(&header.key()[0..usize::from(self.cursor.length)]).unwrap();
-            let nh_key = new_header.key()[0];
-            // shrink key of existing node
-            header.shrink_key(self.cursor.length);
-            //create node with common key part of child and input key newi
-            let mut new_box = NodeBox::newi(new_header, vec![(f_key, node_box)], 0);
-            //remove previous pointer in parent to child: delete
-            let mut parent = self.cursor.parent.unwrap();
-            let prev_child = parent.deref_mut().unwrap().1.left().unwrap().delete(self.cursor.index).ok().unwrap();
-            // update common newly created box to point to old child
-            new_box.deref_mut().unwrap().1.left().unwrap().update(header.key()[0], prev_child).ok().unwrap();
-            //update parent pointer to point to common newly created node
-            parent.deref_mut().unwrap().1.left().unwrap().update(nh_key, new_box);
-            Ok(unsafe{&mut *(node_body_v as *const _ as *mut V)})
-        } else { //no path expansion, just attach
-            // let (header, b) = self.cursor.child.deref_mut().unwrap();
-            let body = b.left().unwrap();
-            let result = body.update(f_key, node_box);
-            match result {
-                Ok((i, n)) => {
-                    Ok(unsafe{&mut *(node_body_v as *const _ as *mut V)})
-                },
-                Err(node) => {
-                    // need to enlarge node
-                    let mut children = body.extract_children();
-                    children.push((f_key, node));
-                    let new_header = NodeHeader::new(&header.key()).unwrap();
-                    let new_box = NodeBox::newi(new_header, children, 0);
-                    let (p_h, p_b) = self.cursor.parent.unwrap().deref_mut().unwrap();
-                    let parent_body = p_b.left().unwrap();
-                    let old_box = parent_body.update(header.key()[0], new_box).ok().unwrap(); //insertion, reference in parent update
-                    drop(old_box);
-                    Ok(unsafe{&mut *(node_body_v as *const _ as *mut V)})
-                },
-            }
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert_with(default),
         }
     }
 
-    /// Inserts the given value if the entry is vacant.
-    ///
-    /// Returns `Ok(v)` if inserted, where `v` is a mutable reference to the inserted value;
-    /// `Err((v, f))` if not inserted, where `v` is a mutable reference to the existing value and
-    /// `f` is the given value generator.
-    pub fn or_insert(self, default: V) -> Result<&'a mut V, (&'a mut V, V)> {
-        self.or_insert_with(|| default).map_err(|(v, f)| (v, f()))
+    /// Ensures a value is in the entry by inserting the result of the default function called with
+    /// the key if empty, and returns a mutable reference to the value in the entry.
+    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce(&K::Owned) -> V,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(&entry.key());
+                entry.insert(value)
+            }
+        }
     }
 
     /// Provides in-place mutable access to an occupied entry before any potential inserts into the
@@ -106,63 +126,183 @@ impl<'a, V, I: 'a + Iterator<Item = u8> + DoubleEndedIterator> Entry<'a, V, I> {
     where
         F: FnOnce(&mut V),
     {
-        if let Some(v) = self.lookup() {
-            f(v);
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
         }
 
         self
     }
 
-    /// Deletes the value at the entry.
-    ///
-    /// Returns `Ok(v)` if the entry contains a value, `v`; `Err(())` if the entry does not contain
-    /// a value.
-    pub fn delete(mut self) -> Result<V, ()> {
-        // the value is not in the tree
-        if self.key.peek() != None {
-            return Err(());
+    /// Returns the key of the entry.
+    pub fn key(&self) -> K::Owned {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
         }
-        let deleted_child = self.cursor.parent.unwrap().deref_mut().unwrap().1.left().unwrap().delete(self.cursor.index).ok().unwrap();
-        Ok(deleted_child.into_value()) // should be called on cursor.child which should be leaf; into_value handles dropping the leaf
     }
+}
 
-    /// Lookups the entry's value.
-    pub fn lookup(&mut self) -> Option<&mut V> {
-        if self.key.peek().is_some() {
-            return None;
-        }
+impl<'a, V: Default, K: ?Sized + ArtKey> Entry<'a, V, K> {
+    /// Ensures a value is in the entry by inserting the default value if empty, and returns a
+    /// mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
 
+impl<'a, V, K: ?Sized + ArtKey> OccupiedEntry<'a, V, K> {
+    /// Returns the key of the entry.
+    ///
+    /// Unlike `std`, the key is decoded from the tree and returned by value.
+    pub fn key(&self) -> K::Owned {
+        decode_key::<K>(&self.key)
+    }
+
+    /// Returns a reference to the value in the entry.
+    pub fn get(&self) -> &V {
+        let (header, body) = self.cursor.child.deref().unwrap();
+        assert_eq!(self.cursor.length, header.length());
+        body.right().unwrap()
+    }
+
+    /// Returns a mutable reference to the value in the entry.
+    pub fn get_mut(&mut self) -> &mut V {
         let (header, body) = self.cursor.child.deref_mut().unwrap(); //returns NodeBox's inner: (header, b)
         assert_eq!(self.cursor.length, header.length());
-        body.right() //is a reference to the leaf node's value
+        body.right().unwrap() //is a reference to the leaf node's value
+    }
+
+    /// Converts the entry into a mutable reference to its value with the lifetime of the tree.
+    pub fn into_mut(self) -> &'a mut V {
+        self.cursor.child.deref_mut().unwrap().1.right().unwrap()
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the key-value pair out of the entry, and returns it.
+    pub fn remove_entry(mut self) -> (K::Owned, V) {
+        let key = self.key();
+        self.cursor.update_counts(|c| *c -= 1);
+        let mut node = self.cursor.parent.unwrap();
+        let deleted_child = node.deref_mut().unwrap().1.left().unwrap().delete(self.cursor.index).ok().unwrap();
+
+        // Removes the nodes left empty, and then shrinks the lowest remaining node. The root is
+        // never removed nor shrunk.
+        let mut ancestors = self.cursor.ancestors;
+        while let Some((parent, index)) = ancestors.pop() {
+            if node.deref().unwrap().1.left().unwrap().len() > 0 {
+                node.shrink();
+                break;
+            }
+            node = unsafe { &mut *parent };
+            drop(node.deref_mut().unwrap().1.left().unwrap().delete(index));
+        }
+
+        (key, deleted_child.into_value()) // should be called on cursor.child which should be leaf; into_value handles dropping the leaf
     }
 }
 
-impl<V> Default for Art<V> {
+impl<'a, V, K: ?Sized + ArtKey> VacantEntry<'a, V, K> {
+    /// Returns the key of the entry.
+    ///
+    /// Unlike `std`, the key is decoded and returned by value.
+    pub fn key(&self) -> K::Owned {
+        decode_key::<K>(&self.key)
+    }
+
+    /// Takes ownership of the key.
+    pub fn into_key(self) -> K::Owned {
+        self.key()
+    }
+
+    /// Sets the value of the entry, and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.insert_with(|| value)
+    }
+
+    /// Sets the value of the entry to the result of `f`, and returns a mutable reference to it.
+    fn insert_with<F>(mut self, f: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.cursor.update_counts(|c| *c += 1);
+        let (header, b) = self.cursor.child.deref_mut().unwrap();
+        let rest = &self.key[self.cursor.depth..];
+        let f_key = rest[0];
+        // call new path on key, creating new leaf node
+        let (node_box, node_body_v) = NodeBox::new_path(rest.iter().cloned(), f);
+        //path expantion is needed
+        if self.cursor.length != (header.length() as u8) {
+            // create header with common part
+            let new_header = NodeHeader::new(&header.key()[0..usize::from(self.cursor.length)]).unwrap();
+            let nh_key = new_header.key()[0];
+            // shrink key of existing node
+            header.shrink_key(self.cursor.length);
+            //create node with common key part of child and input key newi
+            let mut new_box = NodeBox::newi(new_header, vec![(f_key, node_box)], 0);
+            //remove previous pointer in parent to child: delete
+            let parent = self.cursor.parent.unwrap();
+            let prev_child = parent.deref_mut().unwrap().1.left().unwrap().delete(self.cursor.index).ok().unwrap();
+            // update common newly created box to point to old child
+            let (new_header, new_body) = new_box.deref_mut().unwrap();
+            *new_header.count_mut() += prev_child.count();
+            new_body.left().unwrap().update(header.key()[0], prev_child).ok().unwrap();
+            //update parent pointer to point to common newly created node
+            parent.deref_mut().unwrap().1.left().unwrap().update(nh_key, new_box);
+            unsafe{&mut *(node_body_v as *const _ as *mut V)}
+        } else { //no path expansion, just attach
+            let body = b.left().unwrap();
+            let result = body.update(f_key, node_box);
+            if result.is_ok() {
+                *header.count_mut() += 1;
+            }
+            if let Err(node) = result {
+                // need to enlarge node
+                let mut children = body.extract_children();
+                children.push((f_key, node));
+                let new_header = NodeHeader::new(&header.key()).unwrap();
+                let new_box = NodeBox::newi(new_header, children, 0);
+                let (_, p_b) = self.cursor.parent.unwrap().deref_mut().unwrap();
+                let parent_body = p_b.left().unwrap();
+                let old_box = parent_body.update(header.key()[0], new_box).ok().unwrap(); //insertion, reference in parent update
+                drop(old_box);
+            }
+            unsafe{&mut *(node_body_v as *const _ as *mut V)}
+        }
+    }
+}
+
+impl<V, K: ?Sized> Default for Art<V, K> {
     fn default() -> Self {
         Self {
             root: NodeBox::newi(NodeHeader::default(), vec![], 256),
+            _marker: PhantomData,
         }
     }
 }
 
 impl<V> Art<V> {
-    /// Encodes a given string into an array of `u8`. Appending a sentinel value (0xff) to make sure
-    /// a string is not a prefix of another.
-    fn encode_key<'a>(key: &'a str) -> impl 'a + Iterator<Item = u8> + DoubleEndedIterator {
-        key.bytes().chain(vec![KEY_ENDMARK].into_iter())
-    }
-
     /// Creates an adaptive radix tree.
     pub fn new() -> Self {
         Self::default()
     }
+}
 
+impl<V, K: ?Sized + ArtKey> Art<V, K> {
     fn cursor<'a, I>(&'a mut self, key: &mut Peekable<I>) -> Cursor<'a, V> //(ART, whole key) -> Cursor
     where 
-        I: 'a + Iterator<Item = u8>,
+        I: Iterator<Item = u8>,
     {
         let mut cur_node = &mut self.root;
+        let mut ancestors = vec![];
         let mut parent = None;
         let mut length = 0;
         let mut depth = 0;
@@ -197,6 +337,9 @@ impl<V> Art<V> {
             let result = body.lookup_mut(*key.peek().unwrap()); //might fail if key doesn't exist
             match result {
                 Some((i, n)) => {
+                    if let Some(parent) = parent.take() {
+                        ancestors.push((parent as *mut NodeBox<V>, index));
+                    }
                     index = i;
                     parent = Some(unsafe {&mut *(cur_node as *mut NodeBox<V>)});
                     cur_node = n; //moving to the next box
@@ -209,6 +352,7 @@ impl<V> Art<V> {
         //return Cursor here
         Cursor {
                 depth: depth,
+                ancestors: ancestors,
                 parent: parent,
                 child: cur_node,
                 index: index,
@@ -216,35 +360,935 @@ impl<V> Art<V> {
             }
     }
 
+    /// Gets an iterator over the entries of the tree, sorted by key.
+    pub fn iter(&self) -> Iter<'_, V, K> {
+        Iter::new(&self.root)
+    }
+
+    /// Gets a mutable iterator over the entries of the tree, sorted by key.
+    pub fn iter_mut(&mut self) -> IterMut<'_, V, K> {
+        IterMut::new(&mut self.root)
+    }
+
+    /// Gets an iterator over the keys of the tree, in sorted order.
+    pub fn keys(&self) -> Keys<'_, V, K> {
+        Keys::new(self.iter())
+    }
+
+    /// Gets an iterator over the values of the tree, sorted by key.
+    pub fn values(&self) -> Values<'_, V, K> {
+        Values::new(self.iter())
+    }
+
+    /// Gets an iterator over the entries of the tree whose keys start with `prefix`, sorted by key.
+    ///
+    /// Only the subtree of the node matching `prefix` is visited, even if `prefix` ends in the
+    /// middle of the node's header.
+    ///
+    /// The prefix is matched against the encodings of keys, e.g., a tuple `(a, b)` starts with the
+    /// encoding of `a` given by `ArtKey::encode_nested()`.
+    pub fn prefix_iter<Q: ?Sized + ArtKey>(&self, prefix: &Q) -> Iter<'_, V, K> {
+        let prefix = Self::encode_prefix(prefix);
+        match self.prefix_node(&prefix) {
+            Some((node, base)) => Iter::with_prefix(Some(node), &prefix[0..base]),
+            None => Iter::with_prefix(None, &[]),
+        }
+    }
+
+    /// Returns the number of entries in the tree whose keys start with `prefix`.
+    ///
+    /// It visits only the nodes on the path of `prefix`.
+    pub fn count_prefix<Q: ?Sized + ArtKey>(&self, prefix: &Q) -> usize {
+        self.prefix_node(&Self::encode_prefix(prefix))
+            .map_or(0, |(node, _)| node.count())
+    }
+
+    /// Encodes a prefix into the escaped bytes of a key without `KEY_ENDMARK`.
+    fn encode_prefix<Q: ?Sized + ArtKey>(prefix: &Q) -> Vec<u8> {
+        let mut bytes = vec![];
+        prefix.encode(&mut bytes);
+        escape(&bytes)
+    }
+
+    /// Lookups the highest node whose subtree contains exactly the keys starting with `prefix`.
+    ///
+    /// Returns `Some((n, l))` if `n` is such a node and `l` is the length of the headers above it;
+    /// `None` if no key starts with `prefix`.
+    fn prefix_node(&self, prefix: &[u8]) -> Option<(&NodeBox<V>, usize)> {
+        self.descend(prefix)
+    }
+
+    /// Descends from the root along `key` as `cursor()` does, but only reading the tree.
+    ///
+    /// Returns `Some((n, l))` if `key` ends in the header of `n`, where `l` is the length of the
+    /// headers above it; `None` if `key` leaves the tree.
+    fn descend(&self, key: &[u8]) -> Option<(&NodeBox<V>, usize)> {
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            let (header, body) = node.deref().unwrap();
+            let rest = &key[depth..];
+            let length = header
+                .key()
+                .iter()
+                .zip(rest)
+                .take_while(|(h, k)| h == k)
+                .count();
+            if length == rest.len() {
+                return Some((node, depth));
+            }
+            if length < header.key().len() {
+                return None;
+            }
+            depth += length;
+            node = body.left()?.lookup(key[depth])?.1;
+        }
+    }
+
+    /// Returns the first entry in the tree, i.e., the entry of the smallest key.
+    pub fn first(&self) -> Option<(K::Owned, &V)> {
+        let (key, leaf) = self.extreme(true)?;
+        Some((decode_key::<K>(&key), leaf.deref().unwrap().1.right().unwrap()))
+    }
+
+    /// Returns the last entry in the tree, i.e., the entry of the largest key.
+    pub fn last(&self) -> Option<(K::Owned, &V)> {
+        let (key, leaf) = self.extreme(false)?;
+        Some((decode_key::<K>(&key), leaf.deref().unwrap().1.right().unwrap()))
+    }
+
+    /// Removes the first entry from the tree, and returns it.
+    pub fn pop_first(&mut self) -> Option<(K::Owned, V)> {
+        let (key, _) = self.extreme(true)?;
+        match self.entry_encoded(key) {
+            Entry::Occupied(entry) => Some(entry.remove_entry()),
+            Entry::Vacant(_) => unreachable!(),
+        }
+    }
+
+    /// Removes the last entry from the tree, and returns it.
+    pub fn pop_last(&mut self) -> Option<(K::Owned, V)> {
+        let (key, _) = self.extreme(false)?;
+        match self.entry_encoded(key) {
+            Entry::Occupied(entry) => Some(entry.remove_entry()),
+            Entry::Vacant(_) => unreachable!(),
+        }
+    }
+
+    /// Descends to the leaf of the smallest key if `first`; of the largest key otherwise.
+    ///
+    /// Returns `Some((k, n))` if `n` is such a leaf of the encoded key `k`; `None` if the tree is
+    /// empty.
+    fn extreme(&self, first: bool) -> Option<(Vec<u8>, &NodeBox<V>)> {
+        let mut node = &self.root;
+        let mut key = vec![];
+        loop {
+            let (header, body) = node.deref().unwrap();
+            key.extend_from_slice(header.key());
+            let body = some_or!(body.left(), return Some((key, node)));
+
+            // `KEY_ENDMARK` precedes all the other keys of children.
+            let child = if first {
+                body.lookup(KEY_ENDMARK).or_else(|| body.lookup_ge(0))
+            } else {
+                body.lookup_le(KEY_ENDMARK - 1)
+                    .or_else(|| body.lookup(KEY_ENDMARK))
+            };
+            node = child?.1;
+        }
+    }
+
+    /// Returns the number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.root.count()
+    }
+
+    /// Returns `true` if the tree contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of entries in the tree whose keys are in `range`.
+    ///
+    /// It visits only the nodes on the paths of the range bounds, and the counts of their children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`, or if range `start == end` and both bounds are `Excluded`.
+    pub fn count_range<Q, R>(&self, range: R) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = Self::encode_bounds(range);
+        self.count_bounds(&start, &end)
+    }
+
+    /// Returns the number of entries in the tree whose keys are in the range from `start` to `end`,
+    /// which bound the keys encoded by `ArtKey::encode()`.
+    fn count_bounds(&self, start: &Bound<Vec<u8>>, end: &Bound<Vec<u8>>) -> usize {
+        // The number of keys less than `key`, or less than or equal to `key` if `inclusive`.
+        let below = |key: &[u8], inclusive: bool| {
+            let (count, found) = self.count_below(&encode_key::<[u8]>(key));
+            count + (found && inclusive) as usize
+        };
+        let start = match start {
+            Bound::Included(key) => below(key, false),
+            Bound::Excluded(key) => below(key, true),
+            Bound::Unbounded => 0,
+        };
+        let end = match end {
+            Bound::Included(key) => below(key, true),
+            Bound::Excluded(key) => below(key, false),
+            Bound::Unbounded => self.len(),
+        };
+        end - start
+    }
+
+    /// Counts the keys less than `key`, which is given by `key::encode_key()`.
+    ///
+    /// Returns `(c, f)`, where `c` is the number of such keys and `f` is whether `key` is in the
+    /// tree.
+    fn count_below(&self, key: &[u8]) -> (usize, bool) {
+        let rank = |b: u8| b.wrapping_add(1);
+        let mut node = &self.root;
+        let mut depth = 0;
+        let mut count = 0;
+        loop {
+            let (header, body) = node.deref().unwrap();
+            for (h, k) in header.key().iter().zip(&key[depth..]) {
+                if h != k {
+                    if rank(*h) < rank(*k) {
+                        count += node.count();
+                    }
+                    return (count, false);
+                }
+            }
+            depth += header.key().len();
+
+            let body = some_or!(body.left(), return (count, true));
+            let index = key[depth];
+            if index != KEY_ENDMARK {
+                if let Some((_, child)) = body.lookup(KEY_ENDMARK) {
+                    count += child.count();
+                }
+                let mut next = body.lookup_ge(0);
+                while let Some((k, child)) = next {
+                    if k >= index {
+                        break;
+                    }
+                    count += child.count();
+                    next = body.lookup_ge(k + 1);
+                }
+            }
+            node = some_or!(body.lookup(index), return (count, false)).1;
+        }
+    }
+
+    /// Constructs a double-ended iterator over a sub-range of entries in the tree, sorted by key.
+    ///
+    /// Only the subtrees overlapping with the range are visited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`, or if range `start == end` and both bounds are `Excluded`.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, V, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = Self::encode_bounds(range);
+        let len = self.count_bounds(&start, &end);
+        Range::new(&self.root, start, end, len)
+    }
+
+    /// Constructs a mutable double-ended iterator over a sub-range of entries in the tree, sorted
+    /// by key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`, or if range `start == end` and both bounds are `Excluded`.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, V, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = Self::encode_bounds(range);
+        let len = self.count_bounds(&start, &end);
+        RangeMut::new(&mut self.root, start, end, len)
+    }
+
+    /// Converts the bounds of a range into the bounds of keys as bytes.
+    fn encode_bounds<Q, R>(range: R) -> (Bound<Vec<u8>>, Bound<Vec<u8>>)
+    where
+        Q: ?Sized + ArtKey,
+        R: RangeBounds<Q>,
+    {
+        let start = Self::encode_bound(range.start_bound());
+        let end = Self::encode_bound(range.end_bound());
+
+        match (&start, &end) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
+                panic!("range start and end are equal and excluded in Art")
+            }
+            (Bound::Included(s), Bound::Included(e))
+            | (Bound::Included(s), Bound::Excluded(e))
+            | (Bound::Excluded(s), Bound::Included(e))
+            | (Bound::Excluded(s), Bound::Excluded(e))
+                if s > e =>
+            {
+                panic!("range start is greater than range end in Art")
+            }
+            _ => {}
+        }
+        (start, end)
+    }
+
+    /// Converts a bound of keys into a bound of keys as bytes.
+    fn encode_bound<Q: ?Sized + ArtKey>(bound: Bound<&Q>) -> Bound<Vec<u8>> {
+        let bytes = |key: &Q| {
+            let mut bytes = vec![];
+            key.encode(&mut bytes);
+            bytes
+        };
+        match bound {
+            Bound::Included(key) => Bound::Included(bytes(key)),
+            Bound::Excluded(key) => Bound::Excluded(bytes(key)),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    /// Returns the entry of the smallest key above `bound`, i.e., the smallest key `>= k` if
+    /// `bound` is `Included(k)` and `> k` if `Excluded(k)`.
+    ///
+    /// Only the nodes on the path of `bound` and their neighbors are visited.
+    pub fn lower_bound<Q>(&self, bound: Bound<&Q>) -> Option<(K::Owned, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        let start = Self::encode_bound(bound);
+        let len = self.count_bounds(&start, &Bound::Unbounded);
+        Range::<V, K>::new(&self.root, start, Bound::Unbounded, len).next()
+    }
+
+    /// Returns the entry of the largest key below `bound`, i.e., the largest key `<= k` if `bound`
+    /// is `Included(k)` and `< k` if `Excluded(k)`.
+    ///
+    /// Only the nodes on the path of `bound` and their neighbors are visited.
+    pub fn upper_bound<Q>(&self, bound: Bound<&Q>) -> Option<(K::Owned, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        let end = Self::encode_bound(bound);
+        let len = self.count_bounds(&Bound::Unbounded, &end);
+        Range::<V, K>::new(&self.root, Bound::Unbounded, end, len).next_back()
+    }
+
+    /// Returns the entry of the largest key less than or equal to `key`.
+    pub fn floor<Q>(&self, key: &Q) -> Option<(K::Owned, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        self.upper_bound(Bound::Included(key))
+    }
+
+    /// Returns the entry of the smallest key greater than or equal to `key`.
+    pub fn ceiling<Q>(&self, key: &Q) -> Option<(K::Owned, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        self.lower_bound(Bound::Included(key))
+    }
+
+    /// Returns the entry whose key is the longest prefix of `key`, including `key` itself.
+    ///
+    /// The prefixes are those of the encodings of keys, as for `prefix_iter()`. It visits only the
+    /// nodes on the path of `key`.
+    pub fn longest_prefix_match<Q>(&self, key: &Q) -> Option<(K::Owned, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        let mut key = encode_key(key);
+        let mut node = &self.root;
+        let mut depth = 0;
+        // The longest match so far, with the length of its key.
+        let mut result = None;
+        'descend: loop {
+            let (header, body) = node.deref().unwrap();
+            for (i, h) in header.key().iter().enumerate() {
+                // The leaf of a key that is a prefix of `key`.
+                if *h == KEY_ENDMARK {
+                    result = Some((depth + i, node));
+                }
+                if key[depth + i] != *h {
+                    break 'descend;
+                }
+            }
+            depth += header.key().len();
+
+            let body = some_or!(body.left(), break);
+            if let Some((_, child)) = body.lookup(KEY_ENDMARK) {
+                result = Some((depth, child));
+            }
+            node = some_or!(body.lookup(key[depth]), break).1;
+        }
+
+        let (length, leaf) = result?;
+        key.truncate(length);
+        key.push(KEY_ENDMARK);
+        Some((decode_key::<K>(&key), leaf.deref().unwrap().1.right().unwrap()))
+    }
+
+    /// Creates a tree from key-value pairs sorted by key, building the nodes bottom-up.
+    ///
+    /// If a key is given more than once, its last value is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys are not sorted.
+    pub fn from_sorted_iter<Q, I>(iter: I) -> Self
+    where
+        Q: Borrow<K>,
+        I: IntoIterator<Item = (Q, V)>,
+    {
+        let mut entries: Vec<(Vec<u8>, Option<V>)> = vec![];
+        let mut last = None;
+        for (key, value) in iter {
+            let mut bytes = vec![];
+            key.borrow().encode(&mut bytes);
+            match last.as_ref().map(|last: &Vec<u8>| last.cmp(&bytes)) {
+                Some(Ordering::Less) | None => {
+                    let mut key = escape(&bytes);
+                    key.push(KEY_ENDMARK);
+                    entries.push((key, Some(value)));
+                    last = Some(bytes);
+                }
+                Some(Ordering::Equal) => entries.last_mut().unwrap().1 = Some(value),
+                Some(Ordering::Greater) => panic!("Art::from_sorted_iter(): keys are not sorted"),
+            }
+        }
+
+        let children = Self::build_children(&mut entries, 0);
+        Self {
+            root: NodeBox::newi(NodeHeader::default(), children, 256),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Builds the children of a node from sorted entries that share the first `depth` bytes.
+    fn build_children(entries: &mut [(Vec<u8>, Option<V>)], depth: usize) -> Vec<(u8, NodeBox<V>)> {
+        let mut children = vec![];
+        let mut entries = entries;
+        while !entries.is_empty() {
+            let key = entries[0].0[depth];
+            let length = entries.iter().take_while(|(k, _)| k[depth] == key).count();
+            let (group, rest) = entries.split_at_mut(length);
+            children.push((key, Self::build(group, depth)));
+            entries = rest;
+        }
+        children
+    }
+
+    /// Builds a node from sorted entries that share the first `depth + 1` bytes, where the node's
+    /// header starts at `depth`.
+    fn build(entries: &mut [(Vec<u8>, Option<V>)], depth: usize) -> NodeBox<V> {
+        if let [(key, value)] = entries {
+            let value = value.take().unwrap();
+            return NodeBox::new_path(key[depth..].iter().cloned(), || value).0;
+        }
+
+        // The keys are distinct and end with `KEY_ENDMARK`, so none is a prefix of another.
+        let (first, last) = (&entries[0].0, &entries[entries.len() - 1].0);
+        let common = first.iter().zip(last).skip(depth).take_while(|(a, b)| a == b).count();
+        let length = cmp::min(common, NodeHeader::MAX_LENGTH);
+        let header = NodeHeader::new(&first[depth..depth + length]).unwrap();
+        let children = if length < common {
+            vec![(first[depth + length], Self::build(entries, depth + length))]
+        } else {
+            Self::build_children(entries, depth + length)
+        };
+        NodeBox::newi(header, children, 0)
+    }
+
+    /// Splits the tree into two at `key`, and returns the tree of the keys greater than or equal
+    /// to `key`.
+    ///
+    /// Only the nodes on the path of `key` are split, and the subtrees beside the path are moved as
+    /// a whole.
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        let children = Self::split_children(&mut self.root, &encode_key(key), 0);
+        Self {
+            root: NodeBox::newi(NodeHeader::default(), children, 256),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves all the entries of `other` into `self`, leaving `other` empty. If a key is in both
+    /// trees, its value in `other` is kept.
+    ///
+    /// The subtrees of `other` are linked into `self` as a whole where `self` has no keys.
+    pub fn append(&mut self, other: &mut Self) {
+        let (header, body) = other.root.deref_mut().unwrap();
+        *header.count_mut() = 0;
+        for (key, child) in body.left().unwrap().extract_children() {
+            Self::insert_child(&mut self.root, key, child, true);
+        }
+    }
+
+    /// Removes the children of `node` whose subtrees have keys greater than or equal to `key`,
+    /// given by `key::encode_key()`, where the headers above the children have length `depth`.
+    ///
+    /// Returns the removed subtrees with their keys.
+    fn split_children(node: &mut NodeBox<V>, key: &[u8], depth: usize) -> Vec<(u8, NodeBox<V>)> {
+        let rank = |b: u8| b.wrapping_add(1);
+        let (header, body) = node.deref_mut().unwrap();
+        let body = body.left().unwrap();
+        let index = key[depth];
+        let mut result = vec![];
+
+        for k in body.keys() {
+            if rank(k) > rank(index) {
+                let (i, _) = body.lookup(k).unwrap();
+                result.push((k, body.delete(i).unwrap()));
+            }
+        }
+
+        if let Some((i, child)) = body.lookup_mut(index) {
+            let (child_header, child_body) = child.deref().unwrap();
+            let mismatch = child_header.key().iter().zip(&key[depth..]).find(|(h, k)| h != k);
+            let moved = match mismatch {
+                Some((h, k)) => rank(*h) > rank(*k),
+                // The leaf of `key` itself.
+                None if child_body.is_right() => true,
+                None => {
+                    let child_header = child_header.clone();
+                    let depth = depth + child_header.key().len();
+                    let children = Self::split_children(child, key, depth);
+                    if !children.is_empty() {
+                        let mut split = NodeBox::newi(child_header, children, 0);
+                        split.shrink();
+                        result.push((index, split));
+                    }
+                    if child.deref().unwrap().1.left().unwrap().len() > 0 {
+                        child.shrink();
+                    } else {
+                        drop(body.delete(i));
+                    }
+                    false
+                }
+            };
+            if moved {
+                result.push((index, body.delete(i).unwrap()));
+            }
+        }
+
+        *header.count_mut() -= result.iter().map(|(_, c)| c.count()).sum::<usize>();
+        result
+    }
+
+    /// Inserts `child` of `key` into the internal `node`, merging it with the existing child of
+    /// `key` if any. If a key is in both, the value in `child` is kept if `replace`.
+    fn insert_child(node: &mut NodeBox<V>, key: u8, child: NodeBox<V>, replace: bool) {
+        let (header, body) = node.deref_mut().unwrap();
+        let body = body.left().unwrap();
+        if let Some((_, existing)) = body.lookup_mut(key) {
+            Self::merge(existing, child, replace);
+        } else if let Err(child) = body.update(key, child) {
+            let mut children = body.extract_children();
+            children.push((key, child));
+            *node = NodeBox::newi(header.clone(), children, 0);
+            return;
+        }
+        node.recount();
+    }
+
+    /// Merges the subtree of `src` into that of `dst`, where their headers start with the same
+    /// byte. If a key is in both, the value in `src` is kept if `replace`.
+    fn merge(dst: &mut NodeBox<V>, mut src: NodeBox<V>, replace: bool) {
+        let (dst_header, dst_body) = dst.deref_mut().unwrap();
+        let (src_header, src_body) = src.deref_mut().unwrap();
+        let common = dst_header
+            .key()
+            .iter()
+            .zip(src_header.key())
+            .take_while(|(d, s)| d == s)
+            .count();
+        let (dst_length, src_length) = (dst_header.key().len(), src_header.key().len());
+
+        if common < dst_length && common < src_length {
+            // Splits the headers at their common prefix.
+            let header = NodeHeader::new(&dst_header.key()[0..common]).unwrap();
+            dst_header.shrink_key(common as u8);
+            src_header.shrink_key(common as u8);
+            let (dst_key, src_key) = (dst_header.key()[0], src_header.key()[0]);
+            let old = mem::replace(dst, NodeBox::null());
+            *dst = NodeBox::newi(header, vec![(dst_key, old), (src_key, src)], 0);
+        } else if common < src_length {
+            src_header.shrink_key(common as u8);
+            let src_key = src_header.key()[0];
+            Self::insert_child(dst, src_key, src, replace);
+        } else if common < dst_length {
+            let old = mem::replace(dst, src);
+            Self::merge(dst, old, !replace);
+        } else if dst_body.is_right() {
+            // The leaves of the same key.
+            if replace {
+                *dst = src;
+            }
+        } else {
+            for (key, child) in src_body.left().unwrap().extract_children() {
+                Self::insert_child(dst, key, child, replace);
+            }
+        }
+    }
+
+    /// Retains only the entries for which `f` returns `true`.
+    ///
+    /// The entries are visited in key order in a single traversal, freeing and shrinking the nodes
+    /// on the way.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K::Owned, &mut V) -> bool,
+    {
+        let mut iter = RawExtractIf::new(&mut self.root);
+        while iter
+            .next(&mut |key, value| !f(&decode_key::<K>(key), value))
+            .is_some()
+        {}
+    }
+
+    /// Removes the entries for which `pred` returns `true`, and returns an iterator over them.
+    ///
+    /// As in `std`, the iterator is lazy: `pred` is called on the entries in key order as the
+    /// iterator is advanced, and the entries not visited are retained if it is dropped.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, V, F, K>
+    where
+        F: FnMut(&K::Owned, &mut V) -> bool,
+    {
+        ExtractIf::new(&mut self.root, pred)
+    }
+
+    /// Removes all the entries, and returns an iterator over them.
+    pub fn drain(&mut self) -> IntoIter<V, K> {
+        mem::take(self).into_iter()
+    }
+
+    /// Writes a snapshot of the tree to `writer`, where `encode` appends the encoding of a value to
+    /// the given bytes.
+    ///
+    /// See the `snapshot` module for the format.
+    pub fn write_snapshot<W, F>(&self, writer: &mut W, encode: F) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(&V, &mut Vec<u8>),
+    {
+        snapshot::write(&self.root, writer, encode)
+    }
+
+    /// Reads a tree from a snapshot written by `write_snapshot()`, where `decode` decodes a value
+    /// from its encoding. The nodes are rebuilt as they are written.
+    ///
+    /// Returns an error of kind `InvalidData` if the snapshot is malformed, e.g., it has an invalid
+    /// key or a node violating the invariants checked by `validate()`, or its version is not
+    /// supported.
+    pub fn read_snapshot<R, F>(reader: &mut R, decode: F) -> io::Result<Self>
+    where
+        R: Read,
+        F: FnMut(&[u8]) -> io::Result<V>,
+    {
+        Ok(Self {
+            root: snapshot::read(reader, decode, K::is_encoding)?,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns the statistics of the shape and the memory usage of the tree.
+    pub fn stats(&self) -> Stats {
+        stats::collect(&self.root)
+    }
+
+    /// Checks the structural invariants of the tree, for debugging.
+    ///
+    /// Returns an error with the path to the first invalid node found, e.g., if a node has a header
+    /// longer than `MAX_LENGTH`, an internal node is empty or is too large for its children, or the
+    /// number of leaves recorded in a node is wrong.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate::validate(&self.root)
+    }
+
+    /// Returns the Graphviz DOT graph of the tree, for debugging.
+    ///
+    /// Each node is labeled with its kind, header key, and the number of leaves or the value, and
+    /// each edge with its key. Bytes out of the printable ASCII are shown as `\xNN`.
+    pub fn to_dot(&self) -> String
+    where
+        V: fmt::Debug,
+    {
+        dump::to_dot(&self.root)
+    }
+
+    /// Returns the ASCII tree of the tree, for debugging.
+    ///
+    /// Each line is a node labeled as in `to_dot()`, and is prefixed with its key in the parent.
+    pub fn dump(&self) -> String
+    where
+        V: fmt::Debug,
+    {
+        dump::to_ascii(&self.root)
+    }
+
     /// Creates an entry.
-    pub fn entry<'a, I>(&'a mut self, key: I) -> Entry<'a, V, I>
+    pub fn entry<Q>(&mut self, key: &Q) -> Entry<'_, V, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        self.entry_encoded(encode_key(key))
+    }
+
+    /// Creates an entry of a key given by `key::encode_key()`.
+    fn entry_encoded(&mut self, key: Vec<u8>) -> Entry<'_, V, K> {
+        let mut rest = key.iter().cloned().peekable();
+        let cursor = self.cursor(&mut rest);
+        if rest.peek().is_some() {
+            Entry::Vacant(VacantEntry { cursor, key, _marker: PhantomData })
+        } else {
+            Entry::Occupied(OccupiedEntry { cursor, key, _marker: PhantomData })
+        }
+    }
+
+    /// Inserts a key-value pair.
+    ///
+    /// Returns `Ok(v)` if inserted, where `v` is a mutable reference to the inserted value;
+    /// `Err((v, value))` if the key is already in the tree, where `v` is a mutable reference to the
+    /// existing value.
+    pub fn try_insert<Q>(&mut self, key: &Q, value: V) -> Result<&mut V, (&mut V, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Err((entry.into_mut(), value)),
+            Entry::Vacant(entry) => Ok(entry.insert(value)),
+        }
+    }
+
+    /// Inserts a key-value pair, replacing the existing value if any.
+    ///
+    /// Returns the replaced value.
+    pub fn insert_or_replace<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Sets the value of a key to `f(v)`, where `v` is the existing value if any.
+    ///
+    /// Returns a mutable reference to the new value.
+    pub fn upsert<Q, F>(&mut self, key: &Q, f: F) -> &mut V
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+        F: FnOnce(Option<&V>) -> V,
+    {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let value = f(Some(entry.get()));
+                entry.insert(value);
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => entry.insert(f(None)),
+        }
+    }
+
+    /// Removes a key from the tree, returning its value if the key was in the tree.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Some(entry.remove()),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    /// Returns a reference to the value of a key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        let (node, _) = self.descend(&encode_key(key))?;
+        node.deref().unwrap().1.right()
+    }
+
+    /// Returns a mutable reference to the value of a key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
+    {
+        match self.entry(key) {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    /// Returns `true` if the tree contains a key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
-        I: 'a + Iterator<Item = u8> + DoubleEndedIterator,
+        K: Borrow<Q>,
+        Q: ?Sized + ArtKey,
     {
-        let mut key = key.peekable();
-        let cursor = self.cursor(&mut key);
-        Entry { cursor, key }
+        self.get(key).is_some()
+    }
+}
+
+impl<V, K: ?Sized + ArtKey> IntoIterator for Art<V, K> {
+    type Item = (K::Owned, V);
+    type IntoIter = IntoIter<V, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.root)
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> IntoIterator for &'a Art<V, K> {
+    type Item = (K::Owned, &'a V);
+    type IntoIter = Iter<'a, V, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> IntoIterator for &'a mut Art<V, K> {
+    type Item = (K::Owned, &'a mut V);
+    type IntoIter = IterMut<'a, V, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<V: Clone, K: ?Sized> Clone for Art<V, K> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            _marker: PhantomData,
+        }
     }
 }
 
-impl<V> SequentialMap<V> for Art<V> {
+impl<V: PartialEq, K: ?Sized + ArtKey> PartialEq for Art<V, K>
+where
+    K::Owned: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<V: Eq, K: ?Sized + ArtKey> Eq for Art<V, K> where K::Owned: Eq {}
+
+impl<V: Hash, K: ?Sized + ArtKey> Hash for Art<V, K>
+where
+    K::Owned: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for entry in self.iter() {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<V: fmt::Debug, K: ?Sized + ArtKey> fmt::Debug for Art<V, K>
+where
+    K::Owned: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V, K: ?Sized + ArtKey, Q: Borrow<K>> FromIterator<(Q, V)> for Art<V, K> {
+    /// Creates a tree from key-value pairs. If a key is given more than once, its last value is
+    /// kept.
+    fn from_iter<I: IntoIterator<Item = (Q, V)>>(iter: I) -> Self {
+        let mut art = Self::default();
+        art.extend(iter);
+        art
+    }
+}
+
+impl<V, K: ?Sized + ArtKey, Q: Borrow<K>> Extend<(Q, V)> for Art<V, K> {
+    /// Inserts key-value pairs, replacing the existing values.
+    fn extend<I: IntoIterator<Item = (Q, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            let _ = self.insert_or_replace(key.borrow(), value);
+        }
+    }
+}
+
+impl<V, K: ?Sized + ArtKey, Q: ?Sized + ArtKey> Index<&Q> for Art<V, K>
+where
+    K: Borrow<Q>,
+{
+    type Output = V;
+
+    /// Returns a reference to the value of a key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not in the tree.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<V, K: ?Sized + ArtKey + Borrow<str>> SequentialMap<V> for Art<V, K> {
     fn insert<'a>(&'a mut self, key: &'a str, value: V) -> Result<&'a mut V, (&'a mut V, V)> {
-        let key = Self::encode_key(key);
-        self.entry(key).or_insert(value)
+        self.try_insert(key, value)
     }
 
     fn delete(&mut self, key: &str) -> Result<V, ()> {
-        let key = Self::encode_key(key);
-        self.entry(key).delete()
+        self.remove(key).ok_or(())
     }
 
     fn lookup<'a>(&'a self, key: &'a str) -> Option<&'a V> {
-        let key = Self::encode_key(key);
-        unsafe {
-            (*(self as *const _ as *mut Self))
-                .entry(key)
-                .lookup()
-                .map(|v| &*(v as *const V))
-        }
+        self.get(key)
+    }
+
+    fn insert_or_replace(&mut self, key: &str, value: V) -> Option<V> {
+        Art::insert_or_replace(self, key, value)
+    }
+
+    fn upsert<'a, F>(&'a mut self, key: &'a str, f: F) -> &'a mut V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        Art::upsert(self, key, f)
     }
 }
@@ -0,0 +1,314 @@
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use crossbeam_epoch::{unprotected, Atomic, Guard, Owned, Shared};
+
+/// The capacities of internal nodes.
+const CAPACITIES: [usize; 4] = [4, 16, 48, 256];
+
+/// Returns the smallest capacity of internal nodes that is at least `len`.
+///
+/// # Panics
+///
+/// Panics if `len` exceeds 256.
+pub fn capacity_for(len: usize) -> usize {
+    *CAPACITIES
+        .iter()
+        .find(|capacity| **capacity >= len)
+        .unwrap_or_else(|| panic!("capacity_for(): invalid length {}", len))
+}
+
+/// Returns the number of children at or below which an internal node of `capacity` is replaced with
+/// a smaller node, as in `NodeBox::shrink_threshold()`.
+pub fn shrink_threshold(capacity: usize) -> usize {
+    match capacity {
+        16 => 3,
+        48 => 12,
+        256 => 37,
+        _ => 0,
+    }
+}
+
+/// Returns the length of the common prefix of `lhs` and `rhs`.
+pub fn common_prefix(lhs: &[u8], rhs: &[u8]) -> usize {
+    lhs.iter().zip(rhs).take_while(|(l, r)| l == r).count()
+}
+
+/// A node of concurrent trees, where `S` is the synchronization state of internal nodes.
+///
+/// Nodes are shared by readers and writers through `crossbeam_epoch` pointers. The key fragment of
+/// an internal node and the whole leaf are immutable; a node is replaced with a new one to change
+/// them.
+#[derive(Debug)]
+pub enum Node<V, S> {
+    /// An internal node.
+    Inner(Inner<V, S>),
+    /// A leaf node.
+    Leaf(Leaf<V>),
+}
+
+/// An internal node.
+#[derive(Debug)]
+pub struct Inner<V, S> {
+    /// The synchronization state, e.g., a lock.
+    pub sync: S,
+    /// The key fragment between the key of the node in its parent and the keys of its children.
+    pub prefix: Box<[u8]>,
+    /// The children.
+    pub children: Children<Node<V, S>>,
+}
+
+/// A leaf node.
+#[derive(Debug)]
+pub struct Leaf<V> {
+    /// The whole key ending with `KEY_ENDMARK`.
+    pub key: Box<[u8]>,
+    /// The value.
+    pub value: V,
+}
+
+/// The children of an internal node of capacity 4, 16, 48, or 256.
+///
+/// The children are read without locks, and written only by the owner of the node. Slots are never
+/// reused so that a reader does not see the key of a slot together with the child of another key.
+/// So a node may become full after deletions, and then it should be replaced with a new node.
+#[derive(Debug)]
+pub struct Children<T> {
+    /// The key of each slot for capacity 4 and 16; one plus the slot of each key, or 0 if there is
+    /// no such slot, for capacity 48; and empty for capacity 256.
+    keys: Box<[AtomicU8]>,
+    /// The child of each slot, or of each key for capacity 256. A deleted child is null.
+    slots: Box<[Atomic<T>]>,
+    /// The number of used slots for capacity 4, 16, and 48.
+    used: AtomicUsize,
+}
+
+impl<T> Children<T> {
+    /// Creates empty children of `capacity`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is not 4, 16, 48, nor 256.
+    pub fn new(capacity: usize) -> Self {
+        let keys = match capacity {
+            4 | 16 => capacity,
+            48 => 256,
+            256 => 0,
+            _ => panic!("Children::new(): invalid capacity {}", capacity),
+        };
+        Self {
+            keys: (0..keys).map(|_| AtomicU8::new(0)).collect(),
+            slots: (0..capacity).map(|_| Atomic::null()).collect(),
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the capacity.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the slot of the child of `key`, if any.
+    fn slot(&self, key: u8, guard: &Guard) -> Option<&Atomic<T>> {
+        match self.capacity() {
+            256 => Some(&self.slots[usize::from(key)]),
+            48 => match self.keys[usize::from(key)].load(Ordering::Acquire) {
+                0 => None,
+                slot => Some(&self.slots[usize::from(slot - 1)]),
+            },
+            _ => {
+                let used = self.used.load(Ordering::Acquire);
+                izip!(&self.keys[..used], &self.slots[..used])
+                    .find(|(k, c)| {
+                        k.load(Ordering::Relaxed) == key
+                            && !c.load(Ordering::Acquire, guard).is_null()
+                    })
+                    .map(|(_, c)| c)
+            }
+        }
+    }
+
+    /// Returns the child of `key`, or null if there is none.
+    pub fn get<'g>(&self, key: u8, guard: &'g Guard) -> Shared<'g, T> {
+        some_or!(self.slot(key, guard), return Shared::null()).load(Ordering::Acquire, guard)
+    }
+
+    /// Returns the keys and the children in ascending order of keys.
+    pub fn entries<'g>(&self, guard: &'g Guard) -> Vec<(u8, Shared<'g, T>)> {
+        let mut result = match self.capacity() {
+            256 => (0..=u8::MAX)
+                .map(|key| {
+                    (
+                        key,
+                        self.slots[usize::from(key)].load(Ordering::Acquire, guard),
+                    )
+                })
+                .collect(),
+            48 => (0..=u8::MAX)
+                .map(|key| (key, self.get(key, guard)))
+                .collect(),
+            _ => {
+                let used = self.used.load(Ordering::Acquire);
+                izip!(&self.keys[..used], &self.slots[..used])
+                    .map(|(k, c)| (k.load(Ordering::Relaxed), c.load(Ordering::Acquire, guard)))
+                    .collect::<Vec<_>>()
+            }
+        };
+        result.retain(|(_, c)| !c.is_null());
+        result.sort_by_key(|(k, _)| *k);
+        result
+    }
+
+    /// Checks if a new child cannot be inserted.
+    pub fn is_full(&self) -> bool {
+        self.capacity() != 256 && self.used.load(Ordering::Relaxed) == self.capacity()
+    }
+
+    /// Inserts `child` for `key`, which does not have a child. The caller should own the node.
+    ///
+    /// Returns `Err(child)` if the children are full.
+    pub fn insert<'g>(&self, key: u8, child: Shared<'g, T>) -> Result<(), Shared<'g, T>> {
+        if self.capacity() == 256 {
+            self.slots[usize::from(key)].store(child, Ordering::Release);
+            return Ok(());
+        }
+        let used = self.used.load(Ordering::Relaxed);
+        if used == self.capacity() {
+            return Err(child);
+        }
+
+        self.slots[used].store(child, Ordering::Release);
+        if self.capacity() == 48 {
+            self.keys[usize::from(key)].store(used as u8 + 1, Ordering::Release);
+        } else {
+            self.keys[used].store(key, Ordering::Relaxed);
+        }
+        self.used.store(used + 1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Replaces the child of `key` with `child`, and returns the original child. The caller should
+    /// own the node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` does not have a child.
+    pub fn replace<'g>(&self, key: u8, child: Shared<'g, T>, guard: &'g Guard) -> Shared<'g, T> {
+        let slot = self.slot(key, guard).unwrap();
+        let old = slot.swap(child, Ordering::AcqRel, guard);
+        assert!(!old.is_null(), "Children::replace(): no child");
+        old
+    }
+
+    /// Deletes the child of `key`, and returns it. The caller should own the node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` does not have a child.
+    pub fn delete<'g>(&self, key: u8, guard: &'g Guard) -> Shared<'g, T> {
+        let old = self.replace(key, Shared::null(), guard);
+        if self.capacity() == 48 {
+            self.keys[usize::from(key)].store(0, Ordering::Release);
+        }
+        old
+    }
+}
+
+impl<V, S: Default> Inner<V, S> {
+    /// Creates an internal node with `prefix` and `children`, whose capacity is the smallest one
+    /// that is at least the number of children and `min_capacity`.
+    pub fn new(
+        prefix: &[u8],
+        children: &[(u8, Shared<'_, Node<V, S>>)],
+        min_capacity: usize,
+    ) -> Self {
+        let result = Self {
+            sync: S::default(),
+            prefix: prefix.into(),
+            children: Children::new(capacity_for(children.len().max(min_capacity))),
+        };
+        for (key, child) in children {
+            result.children.insert(*key, *child).unwrap();
+        }
+        result
+    }
+
+    /// Creates an internal node for two leaves of different keys that agree on `key[..depth]`,
+    /// where `depth` is the length of the keys of its parent and of it in the parent.
+    pub fn split_leaves<'g>(
+        lhs: Shared<'g, Node<V, S>>,
+        rhs: Shared<'g, Node<V, S>>,
+        depth: usize,
+    ) -> Self {
+        let lkey = unsafe { &lhs.deref().leaf().unwrap().key };
+        let rkey = unsafe { &rhs.deref().leaf().unwrap().key };
+        let length = common_prefix(&lkey[depth..], &rkey[depth..]);
+        let index = depth + length;
+        Self::new(
+            &lkey[depth..index],
+            &[(lkey[index], lhs), (rkey[index], rhs)],
+            0,
+        )
+    }
+
+    /// Creates an internal node for a new leaf and a copy of the node `self`, whose key fragment
+    /// diverges from the leaf's key at `self.prefix[length]`, where `depth` is the length of the
+    /// keys of the parent of `self` and of `self` in the parent.
+    pub fn split_prefix<'g>(
+        &self,
+        leaf: Shared<'g, Node<V, S>>,
+        depth: usize,
+        length: usize,
+        guard: &'g Guard,
+    ) -> Self {
+        let key = unsafe { &leaf.deref().leaf().unwrap().key };
+        let copy = Self::new(&self.prefix[length + 1..], &self.children.entries(guard), 0);
+        let copy = Owned::new(Node::Inner(copy)).into_shared(guard);
+        Self::new(
+            &self.prefix[..length],
+            &[(self.prefix[length], copy), (key[depth + length], leaf)],
+            0,
+        )
+    }
+
+    /// Returns the copy of the node with `key` mapped to `child`, or deleted if `child` is null.
+    pub fn copy_with<'g>(&self, key: u8, child: Shared<'g, Node<V, S>>, guard: &'g Guard) -> Self {
+        let mut children = self.children.entries(guard);
+        children.retain(|(k, _)| *k != key);
+        if !child.is_null() {
+            children.push((key, child));
+        }
+        Self::new(&self.prefix, &children, 0)
+    }
+}
+
+impl<V, S> Node<V, S> {
+    /// Creates a new leaf node.
+    pub fn new_leaf(key: &[u8], value: V) -> Self {
+        Node::Leaf(Leaf {
+            key: key.into(),
+            value,
+        })
+    }
+
+    /// Returns the leaf node, if it is.
+    pub fn leaf(&self) -> Option<&Leaf<V>> {
+        match self {
+            Node::Inner(_) => None,
+            Node::Leaf(leaf) => Some(leaf),
+        }
+    }
+}
+
+/// Destroys the children of `node` and their descendants.
+///
+/// # Safety
+///
+/// No other thread may access the descendants.
+pub unsafe fn destroy_children<V, S>(node: &Inner<V, S>) {
+    for (_, child) in node.children.entries(unprotected()) {
+        if let Node::Inner(inner) = child.deref() {
+            destroy_children(inner);
+        }
+        drop(child.into_owned());
+    }
+}
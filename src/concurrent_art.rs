@@ -0,0 +1,504 @@
+use core::fmt;
+
+use crossbeam_epoch::{Guard, Owned, Shared};
+
+use crate::atomic_node::*;
+use crate::key::encode_key;
+use crate::map::ConcurrentMap;
+
+/// A protocol for synchronizing the internal nodes of `ConcurrentArt`.
+///
+/// A thread reads a node from `read()` to `release()`, and writes it from `lock()` to `unlock()`.
+/// The children of a node may be read concurrently with a write, as they are atomic.
+///
+/// # Safety
+///
+/// At most one thread may write a node at a time, and a node must not be written after it is
+/// unlocked as obsolete.
+pub unsafe trait Protocol: Default {
+    /// The token of a read or a write of a node.
+    type Token;
+
+    /// Starts reading the node.
+    ///
+    /// Returns `Err(())` if the operation should restart.
+    fn read(&self) -> Result<Self::Token, ()>;
+
+    /// Checks that what is read from the node since `read()` is consistent.
+    ///
+    /// Returns `Err(())` if the operation should restart.
+    fn check(&self, token: &Self::Token) -> Result<(), ()>;
+
+    /// Finishes reading the node.
+    fn release(&self, token: Self::Token);
+
+    /// Starts writing the node, which is being read with `token`.
+    ///
+    /// Returns `Err(())` if the operation should restart, and then the node is still being read.
+    fn lock(&self, token: &mut Self::Token) -> Result<(), ()>;
+
+    /// Finishes writing the node, marking it obsolete if `obsolete`.
+    fn unlock(&self, token: Self::Token, obsolete: bool);
+}
+
+/// The parent of a node being read, which is needed to replace the node.
+struct Parent<'g, V, P: Protocol> {
+    node: &'g Inner<V, P>,
+    token: P::Token,
+    /// The key of the child.
+    key: u8,
+    /// The child.
+    child: Shared<'g, Node<V, P>>,
+}
+
+impl<'g, V, P: Protocol> fmt::Debug for Parent<'g, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parent")
+            .field("key", &self.key)
+            .field("child", &self.child)
+            .finish()
+    }
+}
+
+impl<'g, V, P: Protocol> Parent<'g, V, P> {
+    /// Starts writing both the parent and its child `node` being read with `token`.
+    ///
+    /// Returns the tokens of the writes, or `Err(())` after releasing the reads.
+    fn lock(
+        mut self,
+        node: &Inner<V, P>,
+        mut token: P::Token,
+        guard: &'g Guard,
+    ) -> Result<(Self, P::Token), ()> {
+        if self.node.sync.lock(&mut self.token).is_err() {
+            self.node.sync.release(self.token);
+            node.sync.release(token);
+            return Err(());
+        }
+        if self.node.children.get(self.key, guard) != self.child
+            || node.sync.lock(&mut token).is_err()
+        {
+            self.node.sync.unlock(self.token, false);
+            node.sync.release(token);
+            return Err(());
+        }
+        Ok((self, token))
+    }
+
+    /// Replaces the child being written with `new`, or deletes it if `new` is null, and finishes
+    /// writing both.
+    fn replace(
+        self,
+        node: &Inner<V, P>,
+        token: P::Token,
+        new: Shared<'g, Node<V, P>>,
+        guard: &'g Guard,
+    ) {
+        let old = if new.is_null() {
+            self.node.children.delete(self.key, guard)
+        } else {
+            self.node.children.replace(self.key, new, guard)
+        };
+        node.sync.unlock(token, true);
+        self.node.sync.unlock(self.token, false);
+        unsafe { guard.defer_destroy(old) };
+    }
+}
+
+/// Starts writing `node` being read with `token`, if its child of `key` is still `child`.
+///
+/// Returns the token of the write, or `Err(())` after releasing the read.
+fn lock<'g, V, P: Protocol>(
+    node: &Inner<V, P>,
+    mut token: P::Token,
+    key: u8,
+    child: Shared<'g, Node<V, P>>,
+    guard: &'g Guard,
+) -> Result<P::Token, ()> {
+    if node.sync.lock(&mut token).is_err() {
+        node.sync.release(token);
+        return Err(());
+    }
+    if node.children.get(key, guard) != child {
+        node.sync.unlock(token, false);
+        return Err(());
+    }
+    Ok(token)
+}
+
+/// Concurrent adaptive radix tree, whose internal nodes are synchronized by the protocol `P`.
+///
+/// The key fragments of internal nodes and the leaves are immutable, and the nodes to change are
+/// replaced with new ones by atomic pointer swaps. The replaced nodes are reclaimed through
+/// `crossbeam_epoch`.
+#[derive(Debug)]
+pub struct ConcurrentArt<V, P: Protocol> {
+    root: Inner<V, P>,
+}
+
+impl<V, P: Protocol> Default for ConcurrentArt<V, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V, P: Protocol> ConcurrentArt<V, P> {
+    /// Creates a new tree.
+    pub fn new() -> Self {
+        Self {
+            root: Inner::new(&[], &[], 256),
+        }
+    }
+
+    /// Lookups the leaf of `key`.
+    fn lookup_leaf<'g>(&'g self, key: &[u8], guard: &'g Guard) -> Option<&'g Leaf<V>> {
+        'restart: loop {
+            let mut node = &self.root;
+            let mut token = ok_or!(node.sync.read(), continue 'restart);
+            let mut depth = 0;
+
+            loop {
+                if !key[depth..].starts_with(&node.prefix) {
+                    let checked = node.sync.check(&token);
+                    node.sync.release(token);
+                    ok_or!(checked, continue 'restart);
+                    return None;
+                }
+                depth += node.prefix.len();
+
+                let child = node.children.get(key[depth], guard);
+                if node.sync.check(&token).is_err() {
+                    node.sync.release(token);
+                    continue 'restart;
+                }
+                let inner = match unsafe { child.as_ref() } {
+                    Some(Node::Inner(inner)) => inner,
+                    child => {
+                        node.sync.release(token);
+                        return child.and_then(Node::leaf).filter(|leaf| *leaf.key == *key);
+                    }
+                };
+
+                let child_token = inner.sync.read();
+                let checked = node.sync.check(&token);
+                node.sync.release(token);
+                match (child_token, checked) {
+                    (Ok(child_token), Ok(())) => token = child_token,
+                    (Ok(child_token), Err(())) => {
+                        inner.sync.release(child_token);
+                        continue 'restart;
+                    }
+                    (Err(()), _) => continue 'restart,
+                }
+                node = inner;
+                depth += 1;
+            }
+        }
+    }
+
+    /// Writes the leaf of `key`. Calls `f(v)` once, where `v` is the existing value if any, and
+    /// sets the value to `v'` if `f(v)` returns `(Some(v'), r)`.
+    ///
+    /// Returns `r`.
+    fn write<F, R>(&self, key: &[u8], f: F, guard: &Guard) -> R
+    where
+        F: FnOnce(Option<&V>) -> (Option<V>, R),
+    {
+        let mut f = Some(f);
+        loop {
+            if let Ok(result) = self.try_write(key, &mut f, guard) {
+                return result;
+            }
+        }
+    }
+
+    /// Tries to write the leaf of `key` as in `write()`, where `f` is taken when it is called.
+    ///
+    /// Returns `Err(())` if the operation should restart.
+    fn try_write<F, R>(&self, key: &[u8], f: &mut Option<F>, guard: &Guard) -> Result<R, ()>
+    where
+        F: FnOnce(Option<&V>) -> (Option<V>, R),
+    {
+        let new_leaf = |value| Owned::new(Node::new_leaf(key, value)).into_shared(guard);
+        let mut parent = None::<Parent<'_, V, P>>;
+        let mut node = &self.root;
+        let mut token = node.sync.read()?;
+        let mut depth = 0;
+
+        loop {
+            let length = common_prefix(&node.prefix, &key[depth..]);
+            if length < node.prefix.len() {
+                // Splits the key fragment of the node.
+                let (parent, token) = parent.unwrap().lock(node, token, guard)?;
+                let (value, result) = (f.take().unwrap())(None);
+                match value {
+                    Some(value) => {
+                        let new = node.split_prefix(new_leaf(value), depth, length, guard);
+                        let new = Owned::new(Node::Inner(new)).into_shared(guard);
+                        parent.replace(node, token, new, guard);
+                    }
+                    None => {
+                        node.sync.unlock(token, false);
+                        parent.node.sync.unlock(parent.token, false);
+                    }
+                }
+                return Ok(result);
+            }
+            depth += length;
+
+            let index = key[depth];
+            let child = node.children.get(index, guard);
+            if node.sync.check(&token).is_err() {
+                release(parent, node, token);
+                return Err(());
+            }
+
+            let inner = match unsafe { child.as_ref() } {
+                Some(Node::Inner(inner)) => inner,
+                None if node.children.is_full() => {
+                    let (parent, token) = parent.unwrap().lock(node, token, guard)?;
+                    if !node.children.get(index, guard).is_null() {
+                        node.sync.unlock(token, false);
+                        parent.node.sync.unlock(parent.token, false);
+                        return Err(());
+                    }
+                    let (value, result) = (f.take().unwrap())(None);
+                    match value {
+                        Some(value) => {
+                            let new = node.copy_with(index, new_leaf(value), guard);
+                            let new = Owned::new(Node::Inner(new)).into_shared(guard);
+                            parent.replace(node, token, new, guard);
+                        }
+                        None => {
+                            node.sync.unlock(token, false);
+                            parent.node.sync.unlock(parent.token, false);
+                        }
+                    }
+                    return Ok(result);
+                }
+                leaf => {
+                    if let Some(parent) = parent {
+                        parent.node.sync.release(parent.token);
+                    }
+                    let token = lock(node, token, index, child, guard)?;
+                    let leaf = leaf.and_then(Node::leaf);
+                    if leaf.is_none() && node.children.is_full() {
+                        node.sync.unlock(token, false);
+                        return Err(());
+                    }
+
+                    let old = leaf.filter(|leaf| *leaf.key == *key);
+                    let (value, result) = (f.take().unwrap())(old.map(|leaf| &leaf.value));
+                    if let Some(value) = value {
+                        match (leaf, old) {
+                            (None, _) => node.children.insert(index, new_leaf(value)).unwrap(),
+                            (Some(_), Some(_)) => {
+                                let _ = node.children.replace(index, new_leaf(value), guard);
+                                unsafe { guard.defer_destroy(child) };
+                            }
+                            (Some(_), None) => {
+                                let new = Inner::split_leaves(child, new_leaf(value), depth + 1);
+                                let new = Owned::new(Node::Inner(new)).into_shared(guard);
+                                let _ = node.children.replace(index, new, guard);
+                            }
+                        }
+                    }
+                    node.sync.unlock(token, false);
+                    return Ok(result);
+                }
+            };
+
+            let child_token = inner.sync.read();
+            if let Some(parent) = parent {
+                parent.node.sync.release(parent.token);
+            }
+            let child_token = match child_token {
+                Ok(child_token) => child_token,
+                Err(()) => {
+                    node.sync.release(token);
+                    return Err(());
+                }
+            };
+            parent = Some(Parent {
+                node,
+                token,
+                key: index,
+                child,
+            });
+            node = inner;
+            token = child_token;
+            depth += 1;
+        }
+    }
+
+    /// Deletes the leaf of `key`, and returns `f(v)` where `v` is its value.
+    fn delete_leaf<F, R>(&self, key: &[u8], f: F, guard: &Guard) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        let mut f = Some(f);
+        loop {
+            if let Ok(result) = self.try_delete_leaf(key, &mut f, guard) {
+                return result;
+            }
+        }
+    }
+
+    /// Tries to delete the leaf of `key` as in `delete_leaf()`, where `f` is taken when it is
+    /// called.
+    ///
+    /// Returns `Err(())` if the operation should restart.
+    fn try_delete_leaf<F, R>(
+        &self,
+        key: &[u8],
+        f: &mut Option<F>,
+        guard: &Guard,
+    ) -> Result<Option<R>, ()>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        let mut parent = None::<Parent<'_, V, P>>;
+        let mut node = &self.root;
+        let mut token = node.sync.read()?;
+        let mut depth = 0;
+
+        loop {
+            if !key[depth..].starts_with(&node.prefix) {
+                let checked = node.sync.check(&token);
+                release(parent, node, token);
+                return checked.map(|_| None);
+            }
+            depth += node.prefix.len();
+
+            let index = key[depth];
+            let child = node.children.get(index, guard);
+            let checked = node.sync.check(&token);
+            let inner = match unsafe { child.as_ref() } {
+                Some(Node::Inner(inner)) if checked.is_ok() => inner,
+                Some(Node::Leaf(leaf)) if checked.is_ok() && *leaf.key == *key => {
+                    let mut rest = node.children.entries(guard);
+                    rest.retain(|(k, _)| *k != index);
+                    let parent = match parent {
+                        Some(parent) if shrinks(node, &rest) => parent,
+                        parent => {
+                            if let Some(parent) = parent {
+                                parent.node.sync.release(parent.token);
+                            }
+                            let token = lock(node, token, index, child, guard)?;
+                            let _ = node.children.delete(index, guard);
+                            node.sync.unlock(token, false);
+                            unsafe { guard.defer_destroy(child) };
+                            return Ok(Some((f.take().unwrap())(&leaf.value)));
+                        }
+                    };
+
+                    let (parent, token) = parent.lock(node, token, guard)?;
+                    let mut current = node.children.entries(guard);
+                    current.retain(|(k, _)| *k != index);
+                    if current != rest || node.children.get(index, guard) != child {
+                        node.sync.unlock(token, false);
+                        parent.node.sync.unlock(parent.token, false);
+                        return Err(());
+                    }
+                    let new = match rest.as_slice() {
+                        [] => Shared::null(),
+                        [(_, c)] if unsafe { c.deref() }.leaf().is_some() => *c,
+                        _ => {
+                            let new = Inner::new(&node.prefix, &rest, 0);
+                            Owned::new(Node::Inner(new)).into_shared(guard)
+                        }
+                    };
+                    parent.replace(node, token, new, guard);
+                    unsafe { guard.defer_destroy(child) };
+                    return Ok(Some((f.take().unwrap())(&leaf.value)));
+                }
+                _ => {
+                    release(parent, node, token);
+                    return checked.map(|_| None);
+                }
+            };
+
+            let child_token = inner.sync.read();
+            if let Some(parent) = parent {
+                parent.node.sync.release(parent.token);
+            }
+            let child_token = match child_token {
+                Ok(child_token) => child_token,
+                Err(()) => {
+                    node.sync.release(token);
+                    return Err(());
+                }
+            };
+            parent = Some(Parent {
+                node,
+                token,
+                key: index,
+                child,
+            });
+            node = inner;
+            token = child_token;
+            depth += 1;
+        }
+    }
+}
+
+/// Finishes reading `node` and its parent if any.
+fn release<V, P: Protocol>(parent: Option<Parent<'_, V, P>>, node: &Inner<V, P>, token: P::Token) {
+    if let Some(parent) = parent {
+        parent.node.sync.release(parent.token);
+    }
+    node.sync.release(token);
+}
+
+/// Checks if the non-root internal `node` should be replaced when it has the `rest` children
+/// after a deletion, i.e., it is empty, has a single leaf, or is too large for the children.
+fn shrinks<V, S>(node: &Inner<V, S>, rest: &[(u8, Shared<'_, Node<V, S>>)]) -> bool {
+    match rest {
+        [] => true,
+        [(_, child)] if unsafe { child.deref() }.leaf().is_some() => true,
+        _ => rest.len() <= shrink_threshold(node.children.capacity()),
+    }
+}
+
+impl<V, P: Protocol> Drop for ConcurrentArt<V, P> {
+    fn drop(&mut self) {
+        unsafe { destroy_children(&self.root) };
+    }
+}
+
+/// The removed values are cloned, because concurrent readers may still access them.
+impl<V: Clone, P: Protocol> ConcurrentMap<V> for ConcurrentArt<V, P> {
+    fn insert<'a>(&'a self, key: &'a str, value: V, guard: &'a Guard) -> Result<(), V> {
+        self.write(
+            &encode_key(key),
+            |old| match old {
+                Some(_) => (None, Err(value)),
+                None => (Some(value), Ok(())),
+            },
+            guard,
+        )
+    }
+
+    fn delete(&self, key: &str, guard: &Guard) -> Result<V, ()> {
+        self.delete_leaf(&encode_key(key), V::clone, guard)
+            .ok_or(())
+    }
+
+    fn lookup<'a, F, R>(&'a self, key: &'a str, guard: &'a Guard, f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        f(self.lookup_leaf(&encode_key(key), guard).map(|l| &l.value))
+    }
+
+    fn insert_or_replace(&self, key: &str, value: V, guard: &Guard) -> Option<V> {
+        self.write(&encode_key(key), |old| (Some(value), old.cloned()), guard)
+    }
+
+    fn upsert<F>(&self, key: &str, f: F, guard: &Guard)
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        self.write(&encode_key(key), |old| (Some(f(old)), ()), guard)
+    }
+}
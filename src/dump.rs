@@ -0,0 +1,110 @@
+use core::fmt::{Debug, Write};
+
+use either::Either;
+
+use crate::node::*;
+
+/// Formats bytes with the printable ASCII characters as they are and the others as `\xNN`.
+fn format_bytes(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for b in bytes {
+        if (b.is_ascii_graphic() || *b == b' ') && *b != b'\\' {
+            result.push(char::from(*b));
+        } else {
+            write!(result, "\\x{:02x}", b).unwrap();
+        }
+    }
+    result
+}
+
+/// Escapes a string for a quoted string of the DOT language.
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Returns the label of a node with its kind, header key, and the number of leaves or the value.
+fn label<V: Debug>(node: &NodeBox<V>) -> String {
+    let (header, body) = node.deref().unwrap();
+    let key = format_bytes(header.key());
+    match body {
+        Either::Left(_) => format!(
+            "Node{} \"{}\" ({} leaves)",
+            node.capacity(),
+            key,
+            header.count()
+        ),
+        Either::Right(value) => format!("Leaf \"{}\" = {:?}", key, value),
+    }
+}
+
+/// Returns the DOT graph of the tree of `root`.
+pub(crate) fn to_dot<V: Debug>(root: &NodeBox<V>) -> String {
+    let mut result = "digraph art {\n".to_string();
+    let mut id = 0;
+    write_dot(root, &mut id, &mut result);
+    result.push_str("}\n");
+    result
+}
+
+/// Writes the nodes and the edges of the subtree of `node`, where `id` is the next identifier of
+/// nodes.
+fn write_dot<V: Debug>(node: &NodeBox<V>, id: &mut usize, result: &mut String) {
+    let node_id = *id;
+    *id += 1;
+    let (_, body) = node.deref().unwrap();
+    let shape = if body.is_left() { "box" } else { "ellipse" };
+    writeln!(
+        result,
+        "    n{} [shape={}, label=\"{}\"];",
+        node_id,
+        shape,
+        escape_dot(&label(node))
+    )
+    .unwrap();
+
+    if let Either::Left(body) = body {
+        for key in body.keys() {
+            writeln!(
+                result,
+                "    n{} -> n{} [label=\"{}\"];",
+                node_id,
+                id,
+                escape_dot(&format_bytes(&[key]))
+            )
+            .unwrap();
+            write_dot(body.lookup(key).unwrap().1, id, result);
+        }
+    }
+}
+
+/// Returns the ASCII tree of the tree of `root`, where each child is prefixed with its key.
+pub(crate) fn to_ascii<V: Debug>(root: &NodeBox<V>) -> String {
+    let mut result = label(root);
+    result.push('\n');
+    write_ascii(root, &mut String::new(), &mut result);
+    result
+}
+
+/// Writes the children of `node`, where `indent` is the prefix of the lines of the children.
+fn write_ascii<V: Debug>(node: &NodeBox<V>, indent: &mut String, result: &mut String) {
+    let body = some_or!(node.deref().unwrap().1.left(), return);
+    let keys = body.keys();
+    for (i, key) in keys.iter().enumerate() {
+        let last = i + 1 == keys.len();
+        let child = body.lookup(*key).unwrap().1;
+        writeln!(
+            result,
+            "{}{}[{}] {}",
+            indent,
+            if last { "`-- " } else { "+-- " },
+            format_bytes(&[*key]),
+            label(child)
+        )
+        .unwrap();
+
+        let length = indent.len();
+        indent.push_str(if last { "    " } else { "|   " });
+        write_ascii(child, indent, result);
+        indent.truncate(length);
+    }
+}
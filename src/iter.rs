@@ -0,0 +1,850 @@
+use core::cmp::{self, Ordering};
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Bound;
+use std::vec;
+
+use either::Either;
+
+use crate::key::*;
+use crate::node::*;
+
+/// Returns the rank of a child's key in the iteration order.
+///
+/// Every key ends with `KEY_ENDMARK`, which appears nowhere else. So a key is a prefix of another
+/// only up to its end mark, and visiting the child of `KEY_ENDMARK` first yields keys in
+/// lexicographic order.
+#[inline]
+fn rank(key: u8) -> u16 {
+    u16::from(key.wrapping_add(1))
+}
+
+/// Lookups the child of the smallest rank in `lo..hi`.
+///
+/// Returns `Some((r, n))` if `n` is such a child of rank `r`.
+fn first_child<V>(body: &dyn NodeBodyI<V>, lo: u16, hi: u16) -> Option<(u16, &NodeBox<V>)> {
+    if lo >= hi {
+        return None;
+    }
+
+    if lo == 0 {
+        if let Some((_, child)) = body.lookup(KEY_ENDMARK) {
+            return Some((0, child));
+        }
+    }
+
+    let (key, child) = body.lookup_ge((cmp::max(lo, 1) - 1) as u8)?;
+    if key == KEY_ENDMARK || rank(key) >= hi {
+        return None;
+    }
+    Some((rank(key), child))
+}
+
+/// Lookups the child of the largest rank in `lo..hi`.
+///
+/// Returns `Some((r, n))` if `n` is such a child of rank `r`.
+fn last_child<V>(body: &dyn NodeBodyI<V>, lo: u16, hi: u16) -> Option<(u16, &NodeBox<V>)> {
+    if lo >= hi {
+        return None;
+    }
+
+    if hi >= 2 {
+        if let Some((key, child)) = body.lookup_le((hi - 2) as u8) {
+            return if rank(key) >= lo {
+                Some((rank(key), child))
+            } else {
+                None
+            };
+        }
+    }
+
+    if lo == 0 {
+        return body.lookup(KEY_ENDMARK).map(|(_, child)| (0, child));
+    }
+    None
+}
+
+/// Decodes the key of a leaf from the concatenation of the headers on its path.
+fn decode_key(key: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(key.last(), Some(&KEY_ENDMARK));
+    unescape(&key[0..key.len() - 1])
+}
+
+/// A node on the path to the next leaf. Its children of ranks in `lo..hi` are not visited yet.
+#[derive(Debug)]
+struct Frame<V> {
+    node: *const NodeBox<V>,
+    /// The length of the key before the node's header.
+    base: usize,
+    lo: u16,
+    hi: u16,
+}
+
+/// An end of a raw iterator.
+#[derive(Debug)]
+struct Side<V> {
+    /// The path to the next leaf.
+    stack: Vec<Frame<V>>,
+    /// The concatenation of the headers on the path.
+    key: Vec<u8>,
+}
+
+impl<V> Side<V> {
+    /// Creates an end at `node`, where `key` is the key before the node's header.
+    fn new(node: Option<*const NodeBox<V>>, key: &[u8]) -> Self {
+        let mut side = Self {
+            stack: vec![],
+            key: key.to_vec(),
+        };
+        if let Some(node) = node {
+            side.push(node);
+        }
+        side
+    }
+
+    fn push(&mut self, node: *const NodeBox<V>) {
+        let (header, _) = unsafe { &*node }.deref().unwrap();
+        self.stack.push(Frame {
+            node,
+            base: self.key.len(),
+            lo: 0,
+            hi: 256,
+        });
+        self.key.extend_from_slice(header.key());
+    }
+
+    fn pop(&mut self) {
+        let frame = self.stack.pop().unwrap();
+        self.key.truncate(frame.base);
+    }
+
+    fn clear(&mut self) {
+        self.stack.clear();
+        self.key.clear();
+    }
+
+    /// Moves to the leaf of the encoded `key`, skipping the subtrees of the keys below `key` if
+    /// `forward`, and those above `key` otherwise.
+    fn seek(&mut self, key: &[u8], forward: bool) {
+        loop {
+            let frame = some_or!(self.stack.last_mut(), return);
+            let (header, body) = unsafe { &*frame.node }.deref().unwrap();
+            let depth = self.key.len();
+            let fragment = &key[cmp::min(frame.base, key.len())..cmp::min(depth, key.len())];
+            let ranks = |key: &[u8]| key.iter().map(|k| rank(*k)).collect::<Vec<_>>();
+            match ranks(header.key()).cmp(&ranks(fragment)) {
+                Ordering::Less => {
+                    if forward {
+                        self.pop();
+                    }
+                    return;
+                }
+                Ordering::Greater => {
+                    if !forward {
+                        self.pop();
+                    }
+                    return;
+                }
+                Ordering::Equal => {}
+            }
+
+            let body = some_or!(body.left(), return);
+            let edge = *some_or!(key.get(depth), return);
+            if forward {
+                frame.lo = rank(edge) + 1;
+            } else {
+                frame.hi = rank(edge);
+            }
+            let (_, child) = some_or!(body.lookup(edge), return);
+            self.push(child);
+        }
+    }
+
+    /// Moves to the next leaf, forward if `forward` and backward otherwise.
+    ///
+    /// Returns `Some((k, n))` if `n` is the next leaf and `k` is its decoded key.
+    fn next(&mut self, forward: bool) -> Option<(Vec<u8>, *const NodeBox<V>)> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let node = frame.node;
+            match unsafe { &*node }.deref().unwrap().1 {
+                Either::Left(body) => {
+                    let child = if forward {
+                        first_child(body, frame.lo, frame.hi)
+                    } else {
+                        last_child(body, frame.lo, frame.hi)
+                    };
+
+                    match child {
+                        Some((rank, child)) => {
+                            if forward {
+                                frame.lo = rank + 1;
+                            } else {
+                                frame.hi = rank;
+                            }
+                            self.push(child);
+                        }
+                        None => self.pop(),
+                    }
+                }
+                Either::Right(_) => {
+                    let key = decode_key(&self.key);
+                    self.pop();
+                    return Some((key, node));
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` if `key` is above the lower `bound`.
+fn above(bound: &Bound<Vec<u8>>, key: &[u8]) -> bool {
+    match bound {
+        Bound::Included(b) => key >= b.as_slice(),
+        Bound::Excluded(b) => key > b.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+/// Returns `true` if `key` is below the upper `bound`.
+fn below(bound: &Bound<Vec<u8>>, key: &[u8]) -> bool {
+    match bound {
+        Bound::Included(b) => key <= b.as_slice(),
+        Bound::Excluded(b) => key < b.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+/// A double-ended iterator over the leaves of a tree, sorted by key.
+///
+/// The keys are the encodings given by `ArtKey::encode()`, unescaped.
+///
+/// The two ends walk the tree independently. The keys yielded so far narrow `start` and `end`, so
+/// the iterator finishes when the two ends meet.
+#[derive(Debug)]
+pub(crate) struct RawIter<V> {
+    front: Side<V>,
+    back: Side<V>,
+    /// The lower bound of the keys not yielded yet.
+    start: Bound<Vec<u8>>,
+    /// The upper bound of the keys not yielded yet.
+    end: Bound<Vec<u8>>,
+    /// The number of the leaves not yielded yet.
+    remaining: usize,
+}
+
+impl<V> RawIter<V> {
+    /// Creates an iterator over the leaves in the subtree of `node`.
+    pub(crate) fn new(node: *const NodeBox<V>) -> Self {
+        Self::with_prefix(Some(node), &[])
+    }
+
+    /// Creates an iterator over the leaves in the subtree of `node`, where `prefix` is the key
+    /// before the node's header. If `node` is `None`, the iterator is empty.
+    pub(crate) fn with_prefix(node: Option<*const NodeBox<V>>, prefix: &[u8]) -> Self {
+        Self {
+            front: Side::new(node, prefix),
+            back: Side::new(node, prefix),
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            remaining: node.map_or(0, |node| unsafe { &*node }.count()),
+        }
+    }
+
+    /// Creates an iterator over the leaves in the subtree of `node` whose keys are in the range
+    /// from `start` to `end`, where `remaining` is the number of such leaves.
+    pub(crate) fn with_bounds(
+        node: *const NodeBox<V>,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+        remaining: usize,
+    ) -> Self {
+        let mut iter = Self::new(node);
+        match &start {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                iter.front.seek(&encode_key::<[u8]>(key), true)
+            }
+            Bound::Unbounded => {}
+        }
+        match &end {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                iter.back.seek(&encode_key::<[u8]>(key), false)
+            }
+            Bound::Unbounded => {}
+        }
+        iter.start = start;
+        iter.end = end;
+        iter.remaining = remaining;
+        iter
+    }
+
+    /// Returns the number of the leaves not yielded yet.
+    pub(crate) fn len(&self) -> usize {
+        self.remaining
+    }
+
+    fn finish(&mut self) {
+        self.front.clear();
+        self.back.clear();
+    }
+
+    /// Returns the next leaf from the front.
+    ///
+    /// Returns `Some((k, n))` if `n` is the leaf and `k` is its decoded key.
+    pub(crate) fn next(&mut self) -> Option<(Vec<u8>, *const NodeBox<V>)> {
+        while let Some((key, node)) = self.front.next(true) {
+            if !above(&self.start, &key) {
+                continue;
+            }
+            if !below(&self.end, &key) {
+                break;
+            }
+            self.start = Bound::Excluded(key.clone());
+            self.remaining -= 1;
+            return Some((key, node));
+        }
+
+        self.finish();
+        None
+    }
+
+    /// Returns the next leaf from the back.
+    ///
+    /// Returns `Some((k, n))` if `n` is the leaf and `k` is its decoded key.
+    pub(crate) fn next_back(&mut self) -> Option<(Vec<u8>, *const NodeBox<V>)> {
+        while let Some((key, node)) = self.back.next(false) {
+            if !below(&self.end, &key) {
+                continue;
+            }
+            if !above(&self.start, &key) {
+                break;
+            }
+            self.end = Bound::Excluded(key.clone());
+            self.remaining -= 1;
+            return Some((key, node));
+        }
+
+        self.finish();
+        None
+    }
+}
+
+/// Returns a reference to the value of a leaf.
+unsafe fn value<'a, V>(node: *const NodeBox<V>) -> &'a V {
+    (*node).deref().unwrap().1.right().unwrap()
+}
+
+/// Returns a mutable reference to the value of a leaf.
+unsafe fn value_mut<'a, V>(node: *const NodeBox<V>) -> &'a mut V {
+    (*(node as *mut NodeBox<V>))
+        .deref_mut()
+        .unwrap()
+        .1
+        .right()
+        .unwrap()
+}
+
+/// An iterator over the entries of an `Art`, sorted by key.
+#[derive(Debug)]
+pub struct Iter<'a, V, K: ?Sized = str> {
+    inner: RawIter<V>,
+    _marker: PhantomData<(&'a V, &'a K)>,
+}
+
+unsafe impl<'a, V: Sync, K: ?Sized + Sync> Send for Iter<'a, V, K> {}
+unsafe impl<'a, V: Sync, K: ?Sized + Sync> Sync for Iter<'a, V, K> {}
+
+impl<'a, V, K: ?Sized> Iter<'a, V, K> {
+    pub(crate) fn new(node: &'a NodeBox<V>) -> Self {
+        Self {
+            inner: RawIter::new(node),
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn with_prefix(node: Option<&'a NodeBox<V>>, prefix: &[u8]) -> Self {
+        Self {
+            inner: RawIter::with_prefix(node.map(|n| n as *const _), prefix),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> Iterator for Iter<'a, V, K> {
+    type Item = (K::Owned, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(key, node)| (K::decode(&key), unsafe { value(node) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.len(), Some(self.inner.len()))
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> DoubleEndedIterator for Iter<'a, V, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(key, node)| (K::decode(&key), unsafe { value(node) }))
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> ExactSizeIterator for Iter<'a, V, K> {}
+
+/// A mutable iterator over the entries of an `Art`, sorted by key.
+#[derive(Debug)]
+pub struct IterMut<'a, V, K: ?Sized = str> {
+    inner: RawIter<V>,
+    _marker: PhantomData<(&'a mut V, &'a K)>,
+}
+
+unsafe impl<'a, V: Send, K: ?Sized + Sync> Send for IterMut<'a, V, K> {}
+unsafe impl<'a, V: Sync, K: ?Sized + Sync> Sync for IterMut<'a, V, K> {}
+
+impl<'a, V, K: ?Sized> IterMut<'a, V, K> {
+    pub(crate) fn new(node: &'a mut NodeBox<V>) -> Self {
+        Self {
+            inner: RawIter::new(node),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> Iterator for IterMut<'a, V, K> {
+    type Item = (K::Owned, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(key, node)| (K::decode(&key), unsafe { value_mut(node) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.len(), Some(self.inner.len()))
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> DoubleEndedIterator for IterMut<'a, V, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(key, node)| (K::decode(&key), unsafe { value_mut(node) }))
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> ExactSizeIterator for IterMut<'a, V, K> {}
+
+/// An iterator over a sub-range of entries of an `Art`, sorted by key.
+#[derive(Debug)]
+pub struct Range<'a, V, K: ?Sized = str> {
+    inner: RawIter<V>,
+    _marker: PhantomData<(&'a V, &'a K)>,
+}
+
+unsafe impl<'a, V: Sync, K: ?Sized + Sync> Send for Range<'a, V, K> {}
+unsafe impl<'a, V: Sync, K: ?Sized + Sync> Sync for Range<'a, V, K> {}
+
+impl<'a, V, K: ?Sized> Range<'a, V, K> {
+    pub(crate) fn new(
+        node: &'a NodeBox<V>,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+        len: usize,
+    ) -> Self {
+        Self {
+            inner: RawIter::with_bounds(node, start, end, len),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> Iterator for Range<'a, V, K> {
+    type Item = (K::Owned, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(key, node)| (K::decode(&key), unsafe { value(node) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.len(), Some(self.inner.len()))
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> DoubleEndedIterator for Range<'a, V, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(key, node)| (K::decode(&key), unsafe { value(node) }))
+    }
+}
+
+/// A mutable iterator over a sub-range of entries of an `Art`, sorted by key.
+#[derive(Debug)]
+pub struct RangeMut<'a, V, K: ?Sized = str> {
+    inner: RawIter<V>,
+    _marker: PhantomData<(&'a mut V, &'a K)>,
+}
+
+unsafe impl<'a, V: Send, K: ?Sized + Sync> Send for RangeMut<'a, V, K> {}
+unsafe impl<'a, V: Sync, K: ?Sized + Sync> Sync for RangeMut<'a, V, K> {}
+
+impl<'a, V, K: ?Sized> RangeMut<'a, V, K> {
+    pub(crate) fn new(
+        node: &'a mut NodeBox<V>,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+        len: usize,
+    ) -> Self {
+        Self {
+            inner: RawIter::with_bounds(node, start, end, len),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> Iterator for RangeMut<'a, V, K> {
+    type Item = (K::Owned, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(key, node)| (K::decode(&key), unsafe { value_mut(node) }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.len(), Some(self.inner.len()))
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> DoubleEndedIterator for RangeMut<'a, V, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next_back()
+            .map(|(key, node)| (K::decode(&key), unsafe { value_mut(node) }))
+    }
+}
+
+/// An iterator over the keys of an `Art`, sorted.
+#[derive(Debug)]
+pub struct Keys<'a, V, K: ?Sized = str> {
+    inner: Iter<'a, V, K>,
+}
+
+impl<'a, V, K: ?Sized> Keys<'a, V, K> {
+    pub(crate) fn new(inner: Iter<'a, V, K>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> Iterator for Keys<'a, V, K> {
+    type Item = K::Owned;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> DoubleEndedIterator for Keys<'a, V, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<'a, V, K: ?Sized + ArtKey> ExactSizeIterator for Keys<'a, V, K> {}
+
+/// An iterator over the values of an `Art`, sorted by key.
+#[derive(Debug)]
+pub struct Values<'a, V, K: ?Sized = str> {
+    inner: Iter<'a, V, K>,
+}
+
+impl<'a, V, K: ?Sized> Values<'a, V, K> {
+    pub(crate) fn new(inner: Iter<'a, V, K>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, V, K: ?Sized> Iterator for Values<'a, V, K> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .inner
+            .next()
+            .map(|(_, node)| unsafe { value(node) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.inner.inner.len(), Some(self.inner.inner.len()))
+    }
+}
+
+impl<'a, V, K: ?Sized> DoubleEndedIterator for Values<'a, V, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner
+            .inner
+            .next_back()
+            .map(|(_, node)| unsafe { value(node) })
+    }
+}
+
+impl<'a, V, K: ?Sized> ExactSizeIterator for Values<'a, V, K> {}
+
+/// The unvisited children of a node, in the reverse order, with the length of the key before the
+/// node's header.
+type Children<V> = (usize, Vec<(u8, NodeBox<V>)>);
+
+/// An owning iterator over the entries of an `Art`, sorted by key.
+#[derive(Debug)]
+pub struct IntoIter<V, K: ?Sized = str> {
+    /// The nodes on the path to the next leaf.
+    stack: Vec<Children<V>>,
+    /// The concatenation of the headers on the path.
+    key: Vec<u8>,
+    _marker: PhantomData<Box<K>>,
+}
+
+impl<V, K: ?Sized> IntoIter<V, K> {
+    pub(crate) fn new(root: NodeBox<V>) -> Self {
+        let mut iter = Self {
+            stack: vec![],
+            key: vec![],
+            _marker: PhantomData,
+        };
+        iter.push(0, root);
+        iter
+    }
+
+    fn push(&mut self, base: usize, node: NodeBox<V>) {
+        let (_, mut children): (NodeHeader, Vec<_>) = node.into();
+        children.sort_by_key(|(k, _)| cmp::Reverse(rank(*k)));
+        self.stack.push((base, children));
+    }
+}
+
+impl<V, K: ?Sized + ArtKey> Iterator for IntoIter<V, K> {
+    type Item = (K::Owned, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (base, children) = self.stack.last_mut()?;
+            let child = match children.pop() {
+                Some((_, child)) => child,
+                None => {
+                    self.key.truncate(*base);
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            let base = self.key.len();
+            let (header, body) = child.deref().unwrap();
+            self.key.extend_from_slice(header.key());
+            if body.is_left() {
+                self.push(base, child);
+                continue;
+            }
+
+            let key = decode_key(&self.key);
+            self.key.truncate(base);
+            return Some((K::decode(&key), child.into_value()));
+        }
+    }
+}
+
+/// A node on the path to the next leaf to visit by `RawExtractIf`. Its children of ranks below
+/// `lo` are visited.
+#[derive(Debug)]
+struct ExtractFrame<V> {
+    node: *mut NodeBox<V>,
+    /// The length of the key before the node's header.
+    base: usize,
+    lo: u16,
+}
+
+/// A traversal removing the leaves of a tree for which a predicate returns `true`, in key order.
+///
+/// Each node is shrunk, or removed if left empty, when all its children are visited or the
+/// traversal is dropped. The root is neither shrunk nor removed.
+#[derive(Debug)]
+pub(crate) struct RawExtractIf<V> {
+    /// The path to the next leaf.
+    stack: Vec<ExtractFrame<V>>,
+    /// The concatenation of the headers on the path.
+    key: Vec<u8>,
+}
+
+impl<V> RawExtractIf<V> {
+    /// Creates a traversal of the tree of the internal node `root`.
+    pub(crate) fn new(root: *mut NodeBox<V>) -> Self {
+        let mut iter = Self {
+            stack: vec![],
+            key: vec![],
+        };
+        iter.push(root);
+        iter
+    }
+
+    fn push(&mut self, node: *mut NodeBox<V>) {
+        let (header, _) = unsafe { &*node }.deref().unwrap();
+        self.stack.push(ExtractFrame {
+            node,
+            base: self.key.len(),
+            lo: 0,
+        });
+        self.key.extend_from_slice(header.key());
+    }
+
+    /// Pops the top node, and shrinks it, or removes it from its parent if it is left empty.
+    fn pop(&mut self) {
+        let frame = self.stack.pop().unwrap();
+        self.key.truncate(frame.base);
+        let parent = some_or!(self.stack.last(), return);
+        let node = unsafe { &mut *frame.node };
+        let (header, body) = node.deref().unwrap();
+        if body.left().unwrap().len() > 0 {
+            node.shrink();
+            return;
+        }
+
+        let edge = header.key()[0];
+        let body = unsafe { &mut *parent.node }
+            .deref_mut()
+            .unwrap()
+            .1
+            .left()
+            .unwrap();
+        let (index, _) = body.lookup_mut(edge).unwrap();
+        drop(body.delete(index));
+    }
+
+    /// Removes the next leaf for which `pred` returns `true`, and returns its value. `pred` is
+    /// given the concatenation of the headers on the path to each leaf.
+    pub(crate) fn next<F>(&mut self, pred: &mut F) -> Option<V>
+    where
+        F: FnMut(&[u8], &mut V) -> bool,
+    {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let body = unsafe { &mut *frame.node }
+                .deref_mut()
+                .unwrap()
+                .1
+                .left()
+                .unwrap();
+            let (rank, _) = some_or!(first_child(body, frame.lo, 256), {
+                self.pop();
+                continue;
+            });
+            frame.lo = rank + 1;
+
+            let (index, child) = body.lookup_mut((rank as u8).wrapping_sub(1)).unwrap();
+            let (header, child_body) = child.deref_mut().unwrap();
+            let value = match child_body {
+                Either::Left(_) => {
+                    let child: *mut NodeBox<V> = child;
+                    self.push(child);
+                    continue;
+                }
+                Either::Right(value) => value,
+            };
+
+            let base = self.key.len();
+            self.key.extend_from_slice(header.key());
+            let removed = pred(&self.key, value);
+            self.key.truncate(base);
+            if !removed {
+                continue;
+            }
+
+            let leaf = body.delete(index).ok().unwrap();
+            for frame in &self.stack {
+                let (header, _) = unsafe { &mut *frame.node }.deref_mut().unwrap();
+                *header.count_mut() -= 1;
+            }
+            return Some(leaf.into_value());
+        }
+    }
+}
+
+impl<V> Drop for RawExtractIf<V> {
+    fn drop(&mut self) {
+        while !self.stack.is_empty() {
+            self.pop();
+        }
+    }
+}
+
+/// An iterator removing the entries of an `Art` for which a predicate returns `true`, sorted by
+/// key. It is returned by `Art::extract_if()`.
+///
+/// The predicate is called on the entries in key order as the iterator is advanced, and the entries
+/// not visited are retained when the iterator is dropped. The tree is traversed once, and each node
+/// is shrunk or freed as the iterator leaves it.
+pub struct ExtractIf<'a, V, F, K: ?Sized + ArtKey = str>
+where
+    F: FnMut(&K::Owned, &mut V) -> bool,
+{
+    inner: RawExtractIf<V>,
+    pred: F,
+    _marker: PhantomData<(&'a mut V, &'a K)>,
+}
+
+unsafe impl<'a, V: Send, F: Send, K: ?Sized + ArtKey + Sync> Send for ExtractIf<'a, V, F, K> where
+    F: FnMut(&K::Owned, &mut V) -> bool
+{
+}
+
+impl<'a, V, F, K: ?Sized + ArtKey> ExtractIf<'a, V, F, K>
+where
+    F: FnMut(&K::Owned, &mut V) -> bool,
+{
+    pub(crate) fn new(root: &'a mut NodeBox<V>, pred: F) -> Self {
+        Self {
+            inner: RawExtractIf::new(root),
+            pred,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, V, F, K: ?Sized + ArtKey> fmt::Debug for ExtractIf<'a, V, F, K>
+where
+    F: FnMut(&K::Owned, &mut V) -> bool,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtractIf")
+            .field("key", &self.inner.key)
+            .finish()
+    }
+}
+
+impl<'a, V, F, K: ?Sized + ArtKey> Iterator for ExtractIf<'a, V, F, K>
+where
+    F: FnMut(&K::Owned, &mut V) -> bool,
+{
+    type Item = (K::Owned, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pred = &mut self.pred;
+        let mut removed = None;
+        let value = self.inner.next(&mut |key, value| {
+            let key = K::decode(&decode_key(key));
+            if !pred(&key, value) {
+                return false;
+            }
+            removed = Some(key);
+            true
+        })?;
+        Some((removed.unwrap(), value))
+    }
+}
@@ -0,0 +1,352 @@
+use core::convert::TryInto;
+use core::mem;
+
+use crate::node::KEY_ENDMARK;
+
+/// Trait for the keys of `Art`.
+///
+/// A key is encoded into bytes, and the order of keys should coincide with the lexicographic order
+/// of their encodings. If `K: Borrow<Q>`, then a `K` and its borrowed `Q` should be encoded into the
+/// same bytes.
+pub trait ArtKey {
+    /// The owned key decoded from bytes.
+    type Owned;
+
+    /// Appends the encoding of the key to `bytes`.
+    fn encode(&self, bytes: &mut Vec<u8>);
+
+    /// Decodes a key from the bytes given by `encode()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is not an encoding of a key.
+    fn decode(bytes: &[u8]) -> Self::Owned;
+
+    /// Appends the encoding of the key to `bytes` so that it is not a prefix of another key's. It is
+    /// used when the key is followed by another key, e.g., in tuples.
+    fn encode_nested(&self, bytes: &mut Vec<u8>) {
+        self.encode(bytes)
+    }
+
+    /// Decodes a key from the front of the bytes given by `encode_nested()`, and advances `bytes`
+    /// past the key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` does not start with an encoding of a key.
+    fn decode_nested(bytes: &mut &[u8]) -> Self::Owned;
+
+    /// Checks if `bytes` is given by `encode()`, i.e., `decode(bytes)` does not panic. It is used to
+    /// reject malformed keys, e.g., in snapshots, so it should not panic.
+    fn is_encoding(bytes: &[u8]) -> bool;
+
+    /// Checks if `bytes` starts with bytes given by `encode_nested()` as `is_encoding()`, and
+    /// advances `bytes` past the key if so. It should not panic.
+    fn is_nested_encoding(bytes: &mut &[u8]) -> bool;
+}
+
+impl ArtKey for [u8] {
+    type Owned = Vec<u8>;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> Self::Owned {
+        bytes.to_vec()
+    }
+
+    /// Escapes `0x00` as `[0x00, 0xff]` and terminates the key with `[0x00, 0x00]`.
+    fn encode_nested(&self, bytes: &mut Vec<u8>) {
+        for b in self {
+            bytes.push(*b);
+            if *b == 0 {
+                bytes.push(0xff);
+            }
+        }
+        bytes.extend_from_slice(&[0, 0]);
+    }
+
+    fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+        let mut result = vec![];
+        loop {
+            match *bytes {
+                [0, 0, rest @ ..] => {
+                    *bytes = rest;
+                    return result;
+                }
+                [0, 0xff, rest @ ..] => {
+                    result.push(0);
+                    *bytes = rest;
+                }
+                [b, rest @ ..] if *b != 0 => {
+                    result.push(*b);
+                    *bytes = rest;
+                }
+                _ => panic!("ArtKey::decode_nested(): unterminated bytes"),
+            }
+        }
+    }
+
+    fn is_encoding(_bytes: &[u8]) -> bool {
+        true
+    }
+
+    fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+        loop {
+            match *bytes {
+                [0, 0, rest @ ..] => {
+                    *bytes = rest;
+                    return true;
+                }
+                [0, 0xff, rest @ ..] => *bytes = rest,
+                [b, rest @ ..] if *b != 0 => *bytes = rest,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl ArtKey for Vec<u8> {
+    type Owned = Vec<u8>;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.as_slice().encode(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Self::Owned {
+        <[u8]>::decode(bytes)
+    }
+
+    fn encode_nested(&self, bytes: &mut Vec<u8>) {
+        self.as_slice().encode_nested(bytes)
+    }
+
+    fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+        <[u8]>::decode_nested(bytes)
+    }
+
+    fn is_encoding(bytes: &[u8]) -> bool {
+        <[u8]>::is_encoding(bytes)
+    }
+
+    fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+        <[u8]>::is_nested_encoding(bytes)
+    }
+}
+
+impl ArtKey for str {
+    type Owned = String;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.as_bytes().encode(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Self::Owned {
+        String::from_utf8(<[u8]>::decode(bytes)).unwrap()
+    }
+
+    fn encode_nested(&self, bytes: &mut Vec<u8>) {
+        self.as_bytes().encode_nested(bytes)
+    }
+
+    fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+        String::from_utf8(<[u8]>::decode_nested(bytes)).unwrap()
+    }
+
+    fn is_encoding(bytes: &[u8]) -> bool {
+        core::str::from_utf8(bytes).is_ok()
+    }
+
+    fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+        let mut rest = *bytes;
+        <[u8]>::is_nested_encoding(&mut rest)
+            && String::from_utf8(<[u8]>::decode_nested(bytes)).is_ok()
+    }
+}
+
+impl ArtKey for String {
+    type Owned = String;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.as_str().encode(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Self::Owned {
+        str::decode(bytes)
+    }
+
+    fn encode_nested(&self, bytes: &mut Vec<u8>) {
+        self.as_str().encode_nested(bytes)
+    }
+
+    fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+        str::decode_nested(bytes)
+    }
+
+    fn is_encoding(bytes: &[u8]) -> bool {
+        str::is_encoding(bytes)
+    }
+
+    fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+        str::is_nested_encoding(bytes)
+    }
+}
+
+/// Implements `ArtKey` for unsigned integers in the big-endian encoding.
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {$(
+        impl ArtKey for $t {
+            type Owned = $t;
+
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode(mut bytes: &[u8]) -> Self::Owned {
+                let result = Self::decode_nested(&mut bytes);
+                assert!(bytes.is_empty(), "ArtKey::decode(): trailing bytes");
+                result
+            }
+
+            fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+                let (int, rest) = bytes.split_at(mem::size_of::<$t>());
+                *bytes = rest;
+                <$t>::from_be_bytes(int.try_into().unwrap())
+            }
+
+            fn is_encoding(bytes: &[u8]) -> bool {
+                bytes.len() == mem::size_of::<$t>()
+            }
+
+            fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+                if bytes.len() < mem::size_of::<$t>() {
+                    return false;
+                }
+                *bytes = &bytes[mem::size_of::<$t>()..];
+                true
+            }
+        }
+    )*};
+}
+
+/// Implements `ArtKey` for signed integers in the big-endian encoding with the sign bit flipped,
+/// so that negative integers precede non-negative ones.
+macro_rules! impl_signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl ArtKey for $t {
+            type Owned = $t;
+
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                ((*self as $u) ^ (<$t>::MIN as $u)).encode(bytes)
+            }
+
+            fn decode(bytes: &[u8]) -> Self::Owned {
+                (<$u>::decode(bytes) ^ (<$t>::MIN as $u)) as $t
+            }
+
+            fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+                (<$u>::decode_nested(bytes) ^ (<$t>::MIN as $u)) as $t
+            }
+
+            fn is_encoding(bytes: &[u8]) -> bool {
+                <$u>::is_encoding(bytes)
+            }
+
+            fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+                <$u>::is_nested_encoding(bytes)
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(u8, u16, u32, u64, u128, usize);
+impl_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+
+/// Implements `ArtKey` for tuples, where all but the last element are nested.
+macro_rules! impl_tuple {
+    ($($t:ident),+ ; $last:ident) => {
+        impl<$($t: ArtKey,)* $last: ArtKey> ArtKey for ($($t,)* $last,) {
+            type Owned = ($($t::Owned,)* $last::Owned,);
+
+            #[allow(non_snake_case)]
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                let ($($t,)* $last,) = self;
+                $($t.encode_nested(bytes);)*
+                $last.encode(bytes);
+            }
+
+            fn decode(mut bytes: &[u8]) -> Self::Owned {
+                ($($t::decode_nested(&mut bytes),)* $last::decode(bytes),)
+            }
+
+            #[allow(non_snake_case)]
+            fn encode_nested(&self, bytes: &mut Vec<u8>) {
+                let ($($t,)* $last,) = self;
+                $($t.encode_nested(bytes);)*
+                $last.encode_nested(bytes);
+            }
+
+            fn decode_nested(bytes: &mut &[u8]) -> Self::Owned {
+                ($($t::decode_nested(bytes),)* $last::decode_nested(bytes),)
+            }
+
+            fn is_encoding(mut bytes: &[u8]) -> bool {
+                $($t::is_nested_encoding(&mut bytes) &&)* $last::is_encoding(bytes)
+            }
+
+            fn is_nested_encoding(bytes: &mut &[u8]) -> bool {
+                $($t::is_nested_encoding(bytes) &&)* $last::is_nested_encoding(bytes)
+            }
+        }
+    };
+}
+
+impl_tuple!(A; B);
+impl_tuple!(A, B; C);
+impl_tuple!(A, B, C; D);
+
+/// The escape byte for storing keys in a tree.
+///
+/// Every byte is a valid key of a child, but `KEY_ENDMARK` terminates a stored key. So `KEY_ESCAPE`
+/// and `KEY_ENDMARK` in a key are stored as `KEY_ESCAPE` followed by their difference from
+/// `KEY_ESCAPE`, which preserves the order of keys. They never appear in UTF-8 strings.
+pub(crate) const KEY_ESCAPE: u8 = 0xfe;
+
+/// Escapes the bytes of a key for storing them in a tree.
+pub(crate) fn escape(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len() + 1);
+    for b in bytes {
+        if *b >= KEY_ESCAPE {
+            result.extend_from_slice(&[KEY_ESCAPE, *b - KEY_ESCAPE]);
+        } else {
+            result.push(*b);
+        }
+    }
+    result
+}
+
+/// Unescapes the bytes of a key stored in a tree.
+pub(crate) fn unescape(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(b) = iter.next() {
+        if *b == KEY_ESCAPE {
+            result.push(KEY_ESCAPE + *iter.next().unwrap());
+        } else {
+            result.push(*b);
+        }
+    }
+    result
+}
+
+/// Encodes a key into the bytes stored in a tree, ending with `KEY_ENDMARK`.
+pub(crate) fn encode_key<Q: ?Sized + ArtKey>(key: &Q) -> Vec<u8> {
+    let mut bytes = vec![];
+    key.encode(&mut bytes);
+    let mut result = escape(&bytes);
+    result.push(KEY_ENDMARK);
+    result
+}
+
+const_assert!(key_escape; KEY_ESCAPE + 1 == KEY_ENDMARK);
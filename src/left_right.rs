@@ -0,0 +1,177 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crossbeam_epoch::Guard;
+use crossbeam_utils::{Backoff, CachePadded};
+use lock::{Lock, RawLock};
+
+use crate::map::{ConcurrentMap, SequentialMap};
+
+/// Two instances of data of type `T` synchronized by the left-right technique, where readers never
+/// block.
+///
+/// Readers read the instance chosen by `left_right`, announcing themselves in the reader counter of
+/// the current version. A writer holding the lock of type `L` writes the other instance, switches
+/// `left_right` to it, waits for the readers of the previous instance by toggling the version, and
+/// then writes the previous instance in the same way. So each write is performed twice.
+pub struct LeftRight<L: RawLock, T> {
+    instances: [UnsafeCell<T>; 2],
+    /// The index of the instance to read.
+    left_right: AtomicUsize,
+    /// The index of the reader counter for new readers.
+    version: AtomicUsize,
+    readers: [CachePadded<AtomicUsize>; 2],
+    writer: Lock<L, ()>,
+}
+
+unsafe impl<L: RawLock, T: Send> Send for LeftRight<L, T> {}
+unsafe impl<L: RawLock, T: Send + Sync> Sync for LeftRight<L, T> {}
+
+impl<L: RawLock, T: Clone> LeftRight<L, T> {
+    /// Creates two instances of `data`.
+    pub fn new(data: T) -> Self {
+        Self {
+            instances: [UnsafeCell::new(data.clone()), UnsafeCell::new(data)],
+            left_right: AtomicUsize::new(0),
+            version: AtomicUsize::new(0),
+            readers: Default::default(),
+            writer: Lock::new(()),
+        }
+    }
+}
+
+impl<L: RawLock, T> LeftRight<L, T> {
+    /// Reads an instance with `f`, without blocking.
+    pub fn read<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        let version = self.version.load(Ordering::SeqCst);
+        let _ = self.readers[version].fetch_add(1, Ordering::SeqCst);
+        let _reader = Reader {
+            counter: &self.readers[version],
+        };
+        let index = self.left_right.load(Ordering::SeqCst);
+        f(unsafe { &*self.instances[index].get() })
+    }
+
+    /// Writes both instances with `f`, which should write them in the same way, i.e., be
+    /// deterministic.
+    ///
+    /// Returns the result of `f` on the first instance.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the panic is propagated after releasing the lock, but the instance being
+    /// written is left as `f` left it. So the two instances may differ afterwards, and reads may
+    /// observe either of them.
+    pub fn write<R, F: FnMut(&mut T) -> R>(&self, mut f: F) -> R {
+        let _writer = self.writer.lock();
+        let index = self.left_right.load(Ordering::SeqCst);
+        let result = f(unsafe { &mut *self.instances[1 - index].get() });
+        self.left_right.store(1 - index, Ordering::SeqCst);
+        self.toggle_version();
+        let _ = f(unsafe { &mut *self.instances[index].get() });
+        result
+    }
+
+    /// Toggles the version, and waits until no reader may read the instance that was chosen before
+    /// the last switch of `left_right`.
+    fn toggle_version(&self) {
+        let backoff = Backoff::new();
+        let version = self.version.load(Ordering::SeqCst);
+        while self.readers[1 - version].load(Ordering::SeqCst) != 0 {
+            backoff.snooze();
+        }
+        self.version.store(1 - version, Ordering::SeqCst);
+        backoff.reset();
+        while self.readers[version].load(Ordering::SeqCst) != 0 {
+            backoff.snooze();
+        }
+    }
+
+    /// Destroys the instances and returns one of them.
+    pub fn into_inner(self) -> T {
+        let [left, right] = self.instances;
+        if self.left_right.into_inner() == 0 {
+            left.into_inner()
+        } else {
+            right.into_inner()
+        }
+    }
+}
+
+/// A reader announced in a reader counter, which leaves the counter when dropped, even if the
+/// reader panics. Otherwise, the writers would wait for it forever.
+struct Reader<'s> {
+    counter: &'s AtomicUsize,
+}
+
+impl Drop for Reader<'_> {
+    fn drop(&mut self) {
+        let _ = self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<L: RawLock, T: Clone + Default> Default for LeftRight<L, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<L: RawLock, T> fmt::Debug for LeftRight<L, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeftRight")
+            .field("left_right", &self.left_right)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+/// Lookups never block, and each write is performed on both instances. So the values inserted to
+/// both instances are cloned.
+impl<V: Clone, L: RawLock, M> ConcurrentMap<V> for LeftRight<L, M>
+where
+    M: SequentialMap<V>,
+{
+    fn insert<'a>(&'a self, key: &'a str, value: V, _guard: &'a Guard) -> Result<(), V> {
+        self.write(|map| {
+            map.insert(key, value.clone())
+                .map(|_| ())
+                .map_err(|(_, v)| v)
+        })
+    }
+
+    fn delete(&self, key: &str, _guard: &Guard) -> Result<V, ()> {
+        self.write(|map| map.delete(key))
+    }
+
+    fn lookup<'a, F, R>(&'a self, key: &'a str, _guard: &'a Guard, f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        self.read(|map| f(map.lookup(key)))
+    }
+
+    fn insert_or_replace(&self, key: &str, value: V, _guard: &Guard) -> Option<V> {
+        self.write(|map| map.insert_or_replace(key, value.clone()))
+    }
+
+    fn upsert<F>(&self, key: &str, f: F, _guard: &Guard)
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        // `f` is called on the first instance, and its result is reused for the second one.
+        let mut f = Some(f);
+        let mut value = None::<V>;
+        self.write(|map| {
+            let new = match &value {
+                Some(value) => value.clone(),
+                None => {
+                    let new = (f.take().unwrap())(map.lookup(key));
+                    value = Some(new.clone());
+                    new
+                }
+            };
+            let _ = map.upsert(key, |_| new);
+        });
+    }
+}
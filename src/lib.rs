@@ -16,8 +16,33 @@ extern crate rand;
 #[macro_use]
 mod utils;
 mod art;
+mod atomic_node;
+mod concurrent_art;
+mod dump;
+mod iter;
+mod key;
+mod left_right;
 mod map;
 mod node;
+mod olc;
+mod plc;
+mod rowex;
+mod rwlock;
+mod sharded;
+pub mod snapshot;
+mod stats;
+mod validate;
 
-pub use art::{Art, Entry};
+pub use art::{Art, Entry, OccupiedEntry, VacantEntry};
+pub use concurrent_art::{ConcurrentArt, Protocol};
+pub use iter::{ExtractIf, IntoIter, Iter, IterMut, Keys, Range, RangeMut, Values};
+pub use key::ArtKey;
+pub use left_right::LeftRight;
 pub use map::{ConcurrentMap, SequentialMap};
+pub use olc::{OlcArt, VersionLock};
+pub use plc::{Plc, PlcArt};
+pub use rowex::{Rowex, RowexArt};
+pub use rwlock::{RawRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard, SpinRwLock};
+pub use sharded::{Partition, ShardedArt};
+pub use stats::{NodeStats, Stats};
+pub use validate::ValidationError;
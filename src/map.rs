@@ -11,6 +11,27 @@ pub trait SequentialMap<V> {
 
     /// Lookups a key.
     fn lookup<'a>(&'a self, key: &'a str) -> Option<&'a V>;
+
+    /// Inserts a key-value pair, replacing the existing value if any.
+    ///
+    /// Returns the replaced value.
+    fn insert_or_replace(&mut self, key: &str, value: V) -> Option<V> {
+        let old = self.delete(key).ok();
+        let _ = self.insert(key, value);
+        old
+    }
+
+    /// Sets the value of a key to `f(v)`, where `v` is the existing value if any.
+    ///
+    /// Returns a mutable reference to the new value.
+    fn upsert<'a, F>(&'a mut self, key: &'a str, f: F) -> &'a mut V
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        let value = f(self.lookup(key));
+        let _ = self.delete(key);
+        self.insert(key, value).ok().unwrap()
+    }
 }
 
 /// Trait for a concurrent key-value map.
@@ -25,6 +46,19 @@ pub trait ConcurrentMap<V> {
     fn lookup<'a, F, R>(&'a self, key: &'a str, guard: &'a Guard, f: F) -> R
     where
         F: FnOnce(Option<&V>) -> R;
+
+    /// Inserts a key-value pair, replacing the existing value if any.
+    ///
+    /// Returns the replaced value.
+    fn insert_or_replace(&self, key: &str, value: V, guard: &Guard) -> Option<V>;
+
+    /// Atomically sets the value of a key to `f(v)`, where `v` is the existing value if any.
+    ///
+    /// No other write of the key may come between reading `v` and writing `f(v)`. E.g., `Lock<L,
+    /// M>` calls `f` while holding the lock, so `f` should not access the map.
+    fn upsert<F>(&self, key: &str, f: F, guard: &Guard)
+    where
+        F: FnOnce(Option<&V>) -> V;
 }
 
 impl<V, L: RawLock, M> ConcurrentMap<V> for Lock<L, M>
@@ -48,4 +82,15 @@ where
     {
         f(self.lock().lookup(key))
     }
+
+    fn insert_or_replace(&self, key: &str, value: V, _guard: &Guard) -> Option<V> {
+        self.lock().insert_or_replace(key, value)
+    }
+
+    fn upsert<F>(&self, key: &str, f: F, _guard: &Guard)
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        self.lock().upsert(key, f);
+    }
 }
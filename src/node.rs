@@ -8,6 +8,7 @@ use either::Either;
 
 /// The sentinel value for index.
 pub const KEY_ENDMARK: u8 = 0xffu8;
+/// The sentinel value for the empty entries of `NodeBody48::indexes`.
 pub const KEY_INVALID: u8 = 0xfeu8;
 
 /// The header of a node.
@@ -17,11 +18,14 @@ pub struct NodeHeader {
     length: u8,
     /// The key fragment of the node used for path compression optimization.
     key: [u8; NodeHeader::MAX_LENGTH],
+    /// The number of leaves in the subtree of an internal node.
+    count: usize,
 }
 
 /// The body of an internal node of capacity 4.
 struct NodeBody4<V> {
-    /// The key for each entry.
+    /// The key for each entry. An entry is occupied iff its child is not null, so every byte is a
+    /// valid key.
     keys: [u8; 4],
 
     /// The child for each entry.
@@ -30,7 +34,8 @@ struct NodeBody4<V> {
 
 /// The body of an internal node of capacity 16.
 struct NodeBody16<V> {
-    /// The key for each entry.
+    /// The key for each entry. An entry is occupied iff its child is not null, so every byte is a
+    /// valid key.
     keys: [u8; 16],
 
     /// The child for each entry.
@@ -79,6 +84,16 @@ pub trait NodeBodyI<V> {
     /// update; `Err(node)` if `node` cannot be inserted due to capacity reasons.
     fn update(&mut self, key: u8, node: NodeBox<V>) -> Result<(u8, NodeBox<V>), NodeBox<V>>;
 
+    /// Lookups the child of the smallest key that is greater than or equal to `key`.
+    ///
+    /// Returns `Some((k, n))` if `n` is the child of such a key `k`.
+    fn lookup_ge(&self, key: u8) -> Option<(u8, &NodeBox<V>)>;
+
+    /// Lookups the child of the largest key that is less than or equal to `key`.
+    ///
+    /// Returns `Some((k, n))` if `n` is the child of such a key `k`.
+    fn lookup_le(&self, key: u8) -> Option<(u8, &NodeBox<V>)>;
+
     /// Deletes the child at the internal `index` obtained from `lookup()`, `lookup_mut()`, or
     /// `update()`.
     ///
@@ -86,10 +101,29 @@ pub trait NodeBodyI<V> {
     /// no such a child.
     fn delete(&mut self, index: u8) -> Result<NodeBox<V>, ()>;
 
+    /// Returns the number of children.
+    fn len(&self) -> usize;
+
+    /// Returns the keys of the children in ascending order.
+    fn keys(&self) -> Vec<u8> {
+        let mut result = vec![];
+        let mut next = self.lookup_ge(0);
+        while let Some((key, _)) = next {
+            result.push(key);
+            next = key.checked_add(1).and_then(|key| self.lookup_ge(key));
+        }
+        result
+    }
+
     /// Extracts children and makes `self` empty.
     ///
     /// Returns children as a vector of pairs of index and node.
     fn extract_children(&mut self) -> Vec<(u8, NodeBox<V>)>;
+
+    /// Checks the consistency of the keys and the children of the body.
+    ///
+    /// Returns `Err(message)` describing the first inconsistency found.
+    fn validate(&self) -> Result<(), String>;
 }
 
 /// An owning pointer to a node.
@@ -100,7 +134,8 @@ pub struct NodeBox<V> {
 }
 
 impl NodeHeader {
-    const MAX_LENGTH: usize = 23;
+    /// The maximum length of the key fragment.
+    pub const MAX_LENGTH: usize = 23;
 
     /// Creates a new header with the given key.
     ///
@@ -137,20 +172,33 @@ impl NodeHeader {
     pub fn key(&self) -> &[u8] {
         &self.key[0..usize::from(self.length)]
     }
+
+    /// Returns the number of leaves in the subtree of the given header's internal node.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns a mutable reference to the number of leaves in the subtree of the given header's
+    /// internal node.
+    #[inline]
+    pub fn count_mut(&mut self) -> &mut usize {
+        &mut self.count
+    }
 }
 
 impl<V> NodeBodyI<V> for NodeBody4<V> {
     fn lookup(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
         izip!(self.keys.iter(), self.children.iter())
             .enumerate()
-            .find(|(_, (k, _))| **k == key)
+            .find(|(_, (k, c))| **k == key && !c.is_null())
             .map(|(i, (_, c))| (i as u8, c))
     }
 
     fn update(&mut self, key: u8, node: NodeBox<V>) -> Result<(u8, NodeBox<V>), NodeBox<V>> {
         if let Some((i, (_, c))) = izip!(self.keys.iter(), self.children.iter_mut())
             .enumerate()
-            .find(|(_, (k, _))| **k == key)
+            .find(|(_, (k, c))| **k == key && !c.is_null())
         {
             let child = mem::replace(c, node);
             return Ok((i as u8, child));
@@ -158,7 +206,7 @@ impl<V> NodeBodyI<V> for NodeBody4<V> {
 
         if let Some((i, (k, c))) = izip!(self.keys.iter_mut(), self.children.iter_mut())
             .enumerate()
-            .find(|(_, (k, _))| **k == KEY_INVALID)
+            .find(|(_, (_, c))| c.is_null())
         {
             *k = key;
             *c = node;
@@ -168,6 +216,20 @@ impl<V> NodeBodyI<V> for NodeBody4<V> {
         Err(node)
     }
 
+    fn lookup_ge(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        izip!(self.keys.iter(), self.children.iter())
+            .filter(|(k, c)| **k >= key && !c.is_null())
+            .min_by_key(|(k, _)| **k)
+            .map(|(k, c)| (*k, c))
+    }
+
+    fn lookup_le(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        izip!(self.keys.iter(), self.children.iter())
+            .filter(|(k, c)| **k <= key && !c.is_null())
+            .max_by_key(|(k, _)| **k)
+            .map(|(k, c)| (*k, c))
+    }
+
     fn delete(&mut self, index: u8) -> Result<NodeBox<V>, ()> {
         let index = usize::from(index);
         if index >= 4 {
@@ -175,7 +237,6 @@ impl<V> NodeBodyI<V> for NodeBody4<V> {
         }
 
         unsafe {
-            *self.keys.get_unchecked_mut(index) = KEY_INVALID;
             Ok(mem::replace(
                 self.children.get_unchecked_mut(index),
                 NodeBox::null(),
@@ -183,31 +244,38 @@ impl<V> NodeBodyI<V> for NodeBody4<V> {
         }
     }
 
+    fn len(&self) -> usize {
+        self.children.iter().filter(|c| !c.is_null()).count()
+    }
+
     fn extract_children(&mut self) -> Vec<(u8, NodeBox<V>)> {
         let mut result = vec![];
-        for (i, c) in izip!(&mut self.keys, &mut self.children) {
-            if *i != KEY_INVALID {
+        for (i, c) in izip!(&self.keys, &mut self.children) {
+            if !c.is_null() {
                 let child = mem::replace(c, NodeBox::null());
                 result.push((*i, child));
-                *i = KEY_INVALID;
             }
         }
         result
     }
+
+    fn validate(&self) -> Result<(), String> {
+        validate_keys(&self.keys, &self.children)
+    }
 }
 
 impl<V> NodeBodyI<V> for NodeBody16<V> {
     fn lookup(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
         izip!(self.keys.iter(), self.children.iter())
             .enumerate()
-            .find(|(_, (k, _))| **k == key)
+            .find(|(_, (k, c))| **k == key && !c.is_null())
             .map(|(i, (_, c))| (i as u8, c))
     }
 
     fn update(&mut self, key: u8, node: NodeBox<V>) -> Result<(u8, NodeBox<V>), NodeBox<V>> {
         if let Some((i, (_, c))) = izip!(self.keys.iter(), self.children.iter_mut())
             .enumerate()
-            .find(|(_, (k, _))| **k == key)
+            .find(|(_, (k, c))| **k == key && !c.is_null())
         {
             let child = mem::replace(c, node);
             return Ok((i as u8, child));
@@ -215,7 +283,7 @@ impl<V> NodeBodyI<V> for NodeBody16<V> {
 
         if let Some((i, (k, c))) = izip!(self.keys.iter_mut(), self.children.iter_mut())
             .enumerate()
-            .find(|(_, (k, _))| **k == KEY_INVALID)
+            .find(|(_, (_, c))| c.is_null())
         {
             *k = key;
             *c = node;
@@ -225,6 +293,20 @@ impl<V> NodeBodyI<V> for NodeBody16<V> {
         Err(node)
     }
 
+    fn lookup_ge(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        izip!(self.keys.iter(), self.children.iter())
+            .filter(|(k, c)| **k >= key && !c.is_null())
+            .min_by_key(|(k, _)| **k)
+            .map(|(k, c)| (*k, c))
+    }
+
+    fn lookup_le(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        izip!(self.keys.iter(), self.children.iter())
+            .filter(|(k, c)| **k <= key && !c.is_null())
+            .max_by_key(|(k, _)| **k)
+            .map(|(k, c)| (*k, c))
+    }
+
     fn delete(&mut self, index: u8) -> Result<NodeBox<V>, ()> {
         let index = usize::from(index);
         if index >= 16 {
@@ -232,7 +314,6 @@ impl<V> NodeBodyI<V> for NodeBody16<V> {
         }
 
         unsafe {
-            *self.keys.get_unchecked_mut(index) = KEY_INVALID;
             Ok(mem::replace(
                 self.children.get_unchecked_mut(index),
                 NodeBox::null(),
@@ -240,17 +321,24 @@ impl<V> NodeBodyI<V> for NodeBody16<V> {
         }
     }
 
+    fn len(&self) -> usize {
+        self.children.iter().filter(|c| !c.is_null()).count()
+    }
+
     fn extract_children(&mut self) -> Vec<(u8, NodeBox<V>)> {
         let mut result = vec![];
-        for (i, c) in izip!(&mut self.keys, &mut self.children) {
-            if *i != KEY_INVALID {
+        for (i, c) in izip!(&self.keys, &mut self.children) {
+            if !c.is_null() {
                 let child = mem::replace(c, NodeBox::null());
                 result.push((*i, child));
-                *i = KEY_INVALID;
             }
         }
         result
     }
+
+    fn validate(&self) -> Result<(), String> {
+        validate_keys(&self.keys, &self.children)
+    }
 }
 
 impl<V> NodeBodyI<V> for NodeBody48<V> {
@@ -291,6 +379,16 @@ impl<V> NodeBodyI<V> for NodeBody48<V> {
         Err(node)
     }
 
+    fn lookup_ge(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        (key..=u8::MAX).find_map(|k| self.lookup(k).map(|(_, c)| (k, c)))
+    }
+
+    fn lookup_le(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        (0..=key)
+            .rev()
+            .find_map(|k| self.lookup(k).map(|(_, c)| (k, c)))
+    }
+
     fn delete(&mut self, index: u8) -> Result<NodeBox<V>, ()> {
         unsafe {
             let index = mem::replace(
@@ -304,6 +402,10 @@ impl<V> NodeBodyI<V> for NodeBody48<V> {
         }
     }
 
+    fn len(&self) -> usize {
+        self.indexes.iter().filter(|i| **i != KEY_INVALID).count()
+    }
+
     fn extract_children(&mut self) -> Vec<(u8, NodeBox<V>)> {
         let mut result = vec![];
         for (i, j) in self.indexes.iter_mut().enumerate() {
@@ -318,6 +420,29 @@ impl<V> NodeBodyI<V> for NodeBody48<V> {
         }
         result
     }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut referenced = [false; 48];
+        for (key, index) in self.indexes.iter().enumerate() {
+            if *index == KEY_INVALID {
+                continue;
+            }
+            let child = self
+                .children
+                .get(usize::from(*index))
+                .ok_or_else(|| format!("key {:#04x} has invalid index {}", key, index))?;
+            if child.is_null() {
+                return Err(format!("key {:#04x} has index {} of a null child", key, index));
+            }
+            if mem::replace(&mut referenced[usize::from(*index)], true) {
+                return Err(format!("index {} is shared by more than one key", index));
+            }
+        }
+        match (0..48).find(|i| !referenced[*i] && !self.children[*i].is_null()) {
+            Some(i) => Err(format!("child at index {} has no key", i)),
+            None => Ok(()),
+        }
+    }
 }
 
 impl<V> NodeBodyI<V> for NodeBody256<V> {
@@ -338,6 +463,16 @@ impl<V> NodeBodyI<V> for NodeBody256<V> {
         Ok((key, child))
     }
 
+    fn lookup_ge(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        (key..=u8::MAX).find_map(|k| self.lookup(k).map(|(_, c)| (k, c)))
+    }
+
+    fn lookup_le(&self, key: u8) -> Option<(u8, &NodeBox<V>)> {
+        (0..=key)
+            .rev()
+            .find_map(|k| self.lookup(k).map(|(_, c)| (k, c)))
+    }
+
     fn delete(&mut self, index: u8) -> Result<NodeBox<V>, ()> {
         unsafe {
             Ok(mem::replace(
@@ -347,6 +482,10 @@ impl<V> NodeBodyI<V> for NodeBody256<V> {
         }
     }
 
+    fn len(&self) -> usize {
+        self.children.iter().filter(|c| !c.is_null()).count()
+    }
+
     fn extract_children(&mut self) -> Vec<(u8, NodeBox<V>)> {
         let mut result = vec![];
         for (i, c) in self.children.iter_mut().enumerate() {
@@ -357,6 +496,22 @@ impl<V> NodeBodyI<V> for NodeBody256<V> {
         }
         result
     }
+
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Checks that the occupied entries of `NodeBody4` or `NodeBody16` have distinct keys.
+fn validate_keys<V>(keys: &[u8], children: &[NodeBox<V>]) -> Result<(), String> {
+    for (i, (key, child)) in izip!(keys, children).enumerate() {
+        if !child.is_null()
+            && izip!(&keys[..i], &children[..i]).any(|(k, c)| !c.is_null() && k == key)
+        {
+            return Err(format!("key {:#04x} is shared by more than one child", key));
+        }
+    }
+    Ok(())
 }
 
 impl<V> Deref for NodeBodyV<V> {
@@ -401,7 +556,8 @@ impl<V> NodeBox<V> {
     /// least as large as `min_size`.
     ///
     /// If more than one child is given for a key, then the last child will be inserted to the node;
-    /// all the previous children are dropped.
+    /// all the previous children are dropped. The count of `header` is set to the number of leaves
+    /// in the children.
     ///
     /// # Panics
     ///
@@ -420,15 +576,22 @@ impl<V> NodeBox<V> {
             panic!("NodeBox::newi(): invalid size {}", size)
         };
 
-        let base = node.deref_mut().unwrap().1.left().unwrap(); //result is internal node
+        let (header, base) = node.deref_mut().unwrap();
+        let base = base.left().unwrap(); //result is internal node
+        header.count = 0;
         for (i, c) in children.into_iter() {
-            base.update(i, c).map_err(|_| ()).unwrap();
+            header.count += c.count();
+            let (_, old) = base.update(i, c).map_err(|_| ()).unwrap();
+            if !old.is_null() {
+                header.count -= old.count();
+            }
         }//adds index -> NodeBox
 
         node
     }
 
-    fn newv(header: NodeHeader, inner: V) -> NodeBox<V> {
+    /// Creates a new leaf `NodeBox` with a given `header` and value.
+    pub fn newv(header: NodeHeader, inner: V) -> NodeBox<V> {
         Self::new_inner::<NodeBodyV<V>>(
             header,
             4,
@@ -468,6 +631,136 @@ impl<V> NodeBox<V> {
         (node, result)  //tuple of the very parent node and the leaf
     }
 
+    /// Shrinks the internal node after some of its children are deleted.
+    ///
+    /// If the node has a single child and their keys fit in a header, the node is merged into the
+    /// child. Otherwise, if the node has few enough children, it is replaced with a smaller node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is not an internal node.
+    pub fn shrink(&mut self) {
+        let threshold = self.shrink_threshold();
+
+        let (header, body) = self.deref_mut().unwrap();
+        let body = body.left().unwrap();
+        let length = body.len();
+        if length == 1 {
+            let (_, child) = body.lookup_ge(0).unwrap();
+            let child_length = child.deref().unwrap().0.length();
+            if usize::from(header.length() + child_length) <= NodeHeader::MAX_LENGTH {
+                let (_, mut child) = body.extract_children().pop().unwrap();
+                let (child_header, _) = child.deref_mut().unwrap();
+                let key = [header.key(), child_header.key()].concat();
+                let count = child_header.count;
+                *child_header = NodeHeader::new(&key).unwrap();
+                child_header.count = count;
+                *self = child;
+                return;
+            }
+        }
+
+        if length <= threshold {
+            let node = Self::newi(header.clone(), body.extract_children(), 0);
+            *self = node;
+        }
+    }
+
+    /// Returns the number of children at or below which the internal node is replaced with a
+    /// smaller node by `shrink()`.
+    pub fn shrink_threshold(&self) -> usize {
+        match self.inner & TAG_MASK {
+            1 => 3,
+            2 => 12,
+            3 => 37,
+            _ => 0,
+        }
+    }
+
+    /// Recomputes the number of leaves in the subtree of the internal node from its children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is not an internal node.
+    pub fn recount(&mut self) {
+        let (header, body) = self.deref_mut().unwrap();
+        let body = body.left().unwrap();
+        header.count = body
+            .keys()
+            .into_iter()
+            .map(|key| body.lookup(key).unwrap().1.count())
+            .sum();
+    }
+
+    /// Returns the capacity of the internal node, i.e., 4, 16, 48, or 256.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is not an internal node.
+    pub fn capacity(&self) -> usize {
+        match self.inner & TAG_MASK {
+            0 => 4,
+            1 => 16,
+            2 => 48,
+            3 => 256,
+            tag => panic!("NodeBox::capacity(): not an internal node of tag {}", tag),
+        }
+    }
+
+    /// Returns the size of the allocation of the node in bytes, including the padding of
+    /// `CachePadded`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is null.
+    pub fn size(&self) -> usize {
+        assert!(!self.is_null(), "NodeBox::size(): null");
+        match self.inner & TAG_MASK {
+            0 => mem::size_of::<CachePadded<(NodeHeader, NodeBody4<V>)>>(),
+            1 => mem::size_of::<CachePadded<(NodeHeader, NodeBody16<V>)>>(),
+            2 => mem::size_of::<CachePadded<(NodeHeader, NodeBody48<V>)>>(),
+            3 => mem::size_of::<CachePadded<(NodeHeader, NodeBody256<V>)>>(),
+            _ => mem::size_of::<CachePadded<(NodeHeader, NodeBodyV<V>)>>(),
+        }
+    }
+
+    /// Checks the invariants of the node, not including its children: the tag is valid, the header
+    /// key fits in `NodeHeader::MAX_LENGTH`, and the body of an internal node is consistent.
+    ///
+    /// Returns `Err(message)` describing the first violation found.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.is_null() {
+            return Err("null node".to_string());
+        }
+        let tag = self.inner & TAG_MASK;
+        if tag > 4 {
+            return Err(format!("invalid tag {}", tag));
+        }
+
+        let (header, body) = self.deref().unwrap();
+        if usize::from(header.length()) > NodeHeader::MAX_LENGTH {
+            return Err(format!("too long header of length {}", header.length()));
+        }
+        match body {
+            Either::Left(body) => body.validate(),
+            Either::Right(_) => Ok(()),
+        }
+    }
+
+    /// Returns the number of leaves in the subtree of the node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if it is null.
+    pub fn count(&self) -> usize {
+        let (header, body) = self.deref().unwrap();
+        if body.is_left() {
+            header.count()
+        } else {
+            1
+        }
+    }
+
     /// Creates a null `NodeBox`.
     pub fn null() -> Self {
         Self {
@@ -508,6 +801,24 @@ impl<V> Into<(NodeHeader, Vec<(u8, NodeBox<V>)>)> for NodeBox<V> {
     }
 }
 
+impl<V: Clone> Clone for NodeBox<V> {
+    /// Clones the subtree of the node, keeping the sizes of the internal nodes.
+    fn clone(&self) -> Self {
+        let (header, body) = some_or!(self.deref(), return Self::null());
+        let body = match body {
+            Either::Left(body) => body,
+            Either::Right(value) => return Self::newv(header.clone(), value.clone()),
+        };
+
+        let children = body
+            .keys()
+            .into_iter()
+            .map(|key| (key, body.lookup(key).unwrap().1.clone()))
+            .collect();
+        Self::newi(header.clone(), children, self.capacity())
+    }
+}
+
 impl<V> Drop for NodeBox<V> {
     fn drop(&mut self) {
         let ptr = self.inner & !TAG_MASK;
@@ -616,7 +927,7 @@ impl<V> NodeBox<V> {
 impl<V> Default for NodeBody4<V> {
     fn default() -> Self {
         Self {
-            keys: [KEY_INVALID; 4],
+            keys: [0; 4],
             children: [
                 NodeBox::null(),
                 NodeBox::null(),
@@ -630,7 +941,7 @@ impl<V> Default for NodeBody4<V> {
 impl<V> Default for NodeBody16<V> {
     fn default() -> Self {
         Self {
-            keys: [KEY_INVALID; 16],
+            keys: [0; 16],
             children: [
                 NodeBox::null(),
                 NodeBox::null(),
@@ -0,0 +1,64 @@
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use crate::concurrent_art::{ConcurrentArt, Protocol};
+
+/// The bit of `VersionLock` marking an obsolete node.
+const OBSOLETE: usize = 1;
+
+/// The bit of `VersionLock` marking a locked node.
+const LOCKED: usize = 2;
+
+/// A version lock for optimistic lock coupling.
+///
+/// The lowest bit marks an obsolete node that is replaced, the next bit is the lock, and the other
+/// bits are the version incremented on each unlock. Readers do not lock nodes but check their
+/// versions, and restart when a node they read is written concurrently.
+#[derive(Debug, Default)]
+pub struct VersionLock {
+    inner: AtomicUsize,
+}
+
+unsafe impl Protocol for VersionLock {
+    type Token = usize;
+
+    fn read(&self) -> Result<usize, ()> {
+        let version = self.inner.load(Ordering::Acquire);
+        if version & (LOCKED | OBSOLETE) != 0 {
+            return Err(());
+        }
+        Ok(version)
+    }
+
+    fn check(&self, version: &usize) -> Result<(), ()> {
+        fence(Ordering::Acquire);
+        if self.inner.load(Ordering::Relaxed) != *version {
+            return Err(());
+        }
+        Ok(())
+    }
+
+    fn release(&self, _version: usize) {}
+
+    /// Locks the node if it is not written since `read()` returned `version`.
+    fn lock(&self, version: &mut usize) -> Result<(), ()> {
+        self.inner
+            .compare_exchange(
+                *version,
+                *version | LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .map(|_| ())
+            .map_err(|_| ())
+    }
+
+    fn unlock(&self, _version: usize, obsolete: bool) {
+        let delta = if obsolete { LOCKED | OBSOLETE } else { LOCKED };
+        let _ = self.inner.fetch_add(delta, Ordering::Release);
+    }
+}
+
+/// Concurrent adaptive radix tree with optimistic lock coupling.
+///
+/// Each internal node has a `VersionLock`. Writers lock only the nodes they write.
+pub type OlcArt<V> = ConcurrentArt<V, VersionLock>;
@@ -0,0 +1,50 @@
+use core::fmt;
+
+use lock::{RawLock, SpinLock};
+
+use crate::concurrent_art::{ConcurrentArt, Protocol};
+
+/// A lock for pessimistic lock coupling.
+///
+/// Both readers and writers lock the nodes they access in the hand-over-hand manner: a thread locks
+/// a child before unlocking its parent. So no operation restarts, and no node is replaced while a
+/// thread waits for its lock, because the thread holds the lock of the parent.
+#[derive(Default)]
+pub struct Plc<L: RawLock = SpinLock> {
+    lock: L,
+}
+
+impl<L: RawLock> fmt::Debug for Plc<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plc").finish()
+    }
+}
+
+unsafe impl<L: RawLock> Protocol for Plc<L> {
+    type Token = L::Token;
+
+    fn read(&self) -> Result<Self::Token, ()> {
+        Ok(self.lock.lock())
+    }
+
+    fn check(&self, _token: &Self::Token) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn release(&self, token: Self::Token) {
+        unsafe { self.lock.unlock(token) };
+    }
+
+    /// The node is already locked by `read()`.
+    fn lock(&self, _token: &mut Self::Token) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn unlock(&self, token: Self::Token, _obsolete: bool) {
+        unsafe { self.lock.unlock(token) };
+    }
+}
+
+/// Concurrent adaptive radix tree with pessimistic lock coupling, where each internal node has a
+/// lock of type `L`.
+pub type PlcArt<V, L = SpinLock> = ConcurrentArt<V, Plc<L>>;
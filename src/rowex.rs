@@ -0,0 +1,61 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use lock::{RawLock, SpinLock};
+
+use crate::concurrent_art::{ConcurrentArt, Protocol};
+
+/// A lock for the read-optimized write exclusion (ROWEX) protocol.
+///
+/// Readers neither lock nodes nor restart, because writers only perform atomic writes that keep
+/// the nodes consistent, e.g., installing a new node by a pointer swap. Writers lock the nodes they
+/// write, and restart if a node is obsolete, i.e., replaced while they wait for its lock.
+#[derive(Default)]
+pub struct Rowex<L: RawLock = SpinLock> {
+    lock: L,
+    obsolete: AtomicBool,
+}
+
+impl<L: RawLock> fmt::Debug for Rowex<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rowex")
+            .field("obsolete", &self.obsolete)
+            .finish()
+    }
+}
+
+unsafe impl<L: RawLock> Protocol for Rowex<L> {
+    /// The token of the lock when the node is written.
+    type Token = Option<L::Token>;
+
+    fn read(&self) -> Result<Self::Token, ()> {
+        Ok(None)
+    }
+
+    fn check(&self, _token: &Self::Token) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn release(&self, _token: Self::Token) {}
+
+    fn lock(&self, token: &mut Self::Token) -> Result<(), ()> {
+        let lock_token = self.lock.lock();
+        if self.obsolete.load(Ordering::Relaxed) {
+            unsafe { self.lock.unlock(lock_token) };
+            return Err(());
+        }
+        *token = Some(lock_token);
+        Ok(())
+    }
+
+    fn unlock(&self, token: Self::Token, obsolete: bool) {
+        if obsolete {
+            self.obsolete.store(true, Ordering::Relaxed);
+        }
+        unsafe { self.lock.unlock(token.unwrap()) };
+    }
+}
+
+/// Concurrent adaptive radix tree with the ROWEX protocol, where each internal node has a lock of
+/// type `L`.
+pub type RowexArt<V, L = SpinLock> = ConcurrentArt<V, Rowex<L>>;
@@ -0,0 +1,255 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crossbeam_epoch::Guard;
+use crossbeam_utils::Backoff;
+
+use crate::map::{ConcurrentMap, SequentialMap};
+
+/// Trait for raw reader-writer locks, as `lock::RawLock` for mutual exclusion locks.
+///
+/// # Safety
+///
+/// A write should exclude all reads and writes, and a read should exclude writes, i.e., while a
+/// write token is not yet released, no other `read()` or `write()` may return, and while a read
+/// token is not yet released, no `write()` may return.
+pub unsafe trait RawRwLock: Default + Send + Sync {
+    /// The token of a shared access.
+    type ReadToken;
+
+    /// The token of an exclusive access.
+    type WriteToken;
+
+    /// Acquires the lock for a shared access.
+    fn read(&self) -> Self::ReadToken;
+
+    /// Releases the lock for a shared access.
+    ///
+    /// # Safety
+    ///
+    /// `token` should be returned by `read()` of this lock, and released only once.
+    unsafe fn read_unlock(&self, token: Self::ReadToken);
+
+    /// Acquires the lock for an exclusive access.
+    fn write(&self) -> Self::WriteToken;
+
+    /// Releases the lock for an exclusive access.
+    ///
+    /// # Safety
+    ///
+    /// `token` should be returned by `write()` of this lock, and released only once.
+    unsafe fn write_unlock(&self, token: Self::WriteToken);
+}
+
+/// The bit of `SpinRwLock` marking a writer that holds or waits for the lock.
+const WRITER: usize = 1;
+
+/// The amount of `SpinRwLock` for each reader.
+const READER: usize = 2;
+
+/// A spinning reader-writer lock that prefers writers.
+///
+/// The lowest bit marks a writer, and the other bits are the number of readers. A writer sets the
+/// bit to block new readers, and waits for the current readers to finish.
+#[derive(Debug, Default)]
+pub struct SpinRwLock {
+    inner: AtomicUsize,
+}
+
+unsafe impl RawRwLock for SpinRwLock {
+    type ReadToken = ();
+    type WriteToken = ();
+
+    fn read(&self) {
+        let backoff = Backoff::new();
+        loop {
+            let state = self.inner.load(Ordering::Relaxed);
+            if state & WRITER == 0
+                && self
+                    .inner
+                    .compare_exchange(state, state + READER, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+            backoff.snooze();
+        }
+    }
+
+    unsafe fn read_unlock(&self, _token: ()) {
+        let _ = self.inner.fetch_sub(READER, Ordering::Release);
+    }
+
+    fn write(&self) {
+        let backoff = Backoff::new();
+        while self.inner.fetch_or(WRITER, Ordering::Acquire) & WRITER != 0 {
+            backoff.snooze();
+        }
+        backoff.reset();
+        while self.inner.load(Ordering::Acquire) != WRITER {
+            backoff.snooze();
+        }
+    }
+
+    unsafe fn write_unlock(&self, _token: ()) {
+        self.inner.store(0, Ordering::Release);
+    }
+}
+
+/// A reader-writer lock protecting data of type `T`.
+pub struct RwLock<L: RawRwLock, T> {
+    lock: L,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<L: RawRwLock, T: Send> Send for RwLock<L, T> {}
+unsafe impl<L: RawRwLock, T: Send + Sync> Sync for RwLock<L, T> {}
+
+impl<L: RawRwLock, T> RwLock<L, T> {
+    /// Creates a new lock protecting `data`.
+    pub fn new(data: T) -> Self {
+        Self {
+            lock: L::default(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Destroys the lock and returns the protected data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Acquires the lock for a shared access, e.g., a lookup or an iteration.
+    pub fn read(&self) -> RwLockReadGuard<'_, L, T> {
+        let token = self.lock.read();
+        RwLockReadGuard {
+            lock: self,
+            token: ManuallyDrop::new(token),
+        }
+    }
+
+    /// Acquires the lock for an exclusive access.
+    pub fn write(&self) -> RwLockWriteGuard<'_, L, T> {
+        let token = self.lock.write();
+        RwLockWriteGuard {
+            lock: self,
+            token: ManuallyDrop::new(token),
+        }
+    }
+}
+
+impl<L: RawRwLock, T: Default> Default for RwLock<L, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<L: RawRwLock, T> fmt::Debug for RwLock<L, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RwLock").finish()
+    }
+}
+
+/// A guard of a shared access to `RwLock`.
+pub struct RwLockReadGuard<'s, L: RawRwLock, T> {
+    lock: &'s RwLock<L, T>,
+    token: ManuallyDrop<L::ReadToken>,
+}
+
+impl<'s, L: RawRwLock, T> Drop for RwLockReadGuard<'s, L, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock
+                .lock
+                .read_unlock(ManuallyDrop::take(&mut self.token))
+        };
+    }
+}
+
+impl<'s, L: RawRwLock, T> Deref for RwLockReadGuard<'s, L, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'s, L: RawRwLock, T: fmt::Debug> fmt::Debug for RwLockReadGuard<'s, L, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+/// A guard of an exclusive access to `RwLock`.
+pub struct RwLockWriteGuard<'s, L: RawRwLock, T> {
+    lock: &'s RwLock<L, T>,
+    token: ManuallyDrop<L::WriteToken>,
+}
+
+impl<'s, L: RawRwLock, T> Drop for RwLockWriteGuard<'s, L, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.lock
+                .lock
+                .write_unlock(ManuallyDrop::take(&mut self.token))
+        };
+    }
+}
+
+impl<'s, L: RawRwLock, T> Deref for RwLockWriteGuard<'s, L, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'s, L: RawRwLock, T> DerefMut for RwLockWriteGuard<'s, L, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'s, L: RawRwLock, T: fmt::Debug> fmt::Debug for RwLockWriteGuard<'s, L, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
+/// Lookups take shared accesses, so they run in parallel.
+impl<V, L: RawRwLock, M> ConcurrentMap<V> for RwLock<L, M>
+where
+    M: SequentialMap<V>,
+{
+    fn insert<'a>(&'a self, key: &'a str, value: V, _guard: &'a Guard) -> Result<(), V> {
+        self.write()
+            .insert(key, value)
+            .map(|_| ())
+            .map_err(|(_, v)| v)
+    }
+
+    fn delete(&self, key: &str, _guard: &Guard) -> Result<V, ()> {
+        self.write().delete(key)
+    }
+
+    fn lookup<'a, F, R>(&'a self, key: &'a str, _guard: &'a Guard, f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        f(self.read().lookup(key))
+    }
+
+    fn insert_or_replace(&self, key: &str, value: V, _guard: &Guard) -> Option<V> {
+        self.write().insert_or_replace(key, value)
+    }
+
+    fn upsert<F>(&self, key: &str, f: F, _guard: &Guard)
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        let _ = self.write().upsert(key, f);
+    }
+}
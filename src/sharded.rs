@@ -0,0 +1,192 @@
+use core::cmp::Reverse;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, RangeBounds};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+
+use crossbeam_epoch::Guard;
+use lock::{Lock, RawLock, SpinLock};
+
+use crate::art::Art;
+use crate::map::ConcurrentMap;
+
+/// How `ShardedArt` routes keys to shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partition {
+    /// By the first two bytes of keys, where each shard owns a contiguous range of them. So the
+    /// shards are ordered, and range scans visit only the shards overlapping with the range.
+    Prefix,
+    /// By the hash of keys, which spreads keys with common prefixes. Range scans merge all shards.
+    Hash,
+}
+
+/// A concurrent map of independent trees behind separate locks of type `L`, one for each shard.
+pub struct ShardedArt<V, L: RawLock = SpinLock> {
+    shards: Box<[Lock<L, Art<V>>]>,
+    partition: Partition,
+}
+
+impl<V, L: RawLock> ShardedArt<V, L> {
+    /// Creates a new map of `shards` shards partitioned by `partition`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0, or if `shards` exceeds 65536 and `partition` is `Prefix`.
+    pub fn new(shards: usize, partition: Partition) -> Self {
+        assert!(
+            shards > 0 && (partition == Partition::Hash || shards <= 1 << 16),
+            "ShardedArt::new(): invalid number of shards {}",
+            shards
+        );
+        Self {
+            shards: (0..shards).map(|_| Lock::new(Art::new())).collect(),
+            partition,
+        }
+    }
+
+    /// Returns the partition of the map.
+    pub fn partition(&self) -> Partition {
+        self.partition
+    }
+
+    /// Returns the number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard of `key`.
+    fn index(&self, key: &str) -> usize {
+        match self.partition {
+            Partition::Prefix => {
+                let bytes = key.as_bytes();
+                let prefix = usize::from(bytes.first().copied().unwrap_or(0)) << 8
+                    | usize::from(bytes.get(1).copied().unwrap_or(0));
+                (prefix * self.shards.len()) >> 16
+            }
+            Partition::Hash => {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                (hasher.finish() % self.shards.len() as u64) as usize
+            }
+        }
+    }
+
+    /// Returns the shard of `key`.
+    fn shard(&self, key: &str) -> &Lock<L, Art<V>> {
+        &self.shards[self.index(key)]
+    }
+
+    /// Returns the number of entries. It is not atomic, as the shards are locked one by one.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().len()).sum()
+    }
+
+    /// Checks if the map is empty. It is not atomic, as the shards are locked one by one.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.lock().is_empty())
+    }
+
+    /// Calls `f` on the entries in `range` in ascending order of keys, merging those of the shards.
+    ///
+    /// For `Partition::Prefix`, the shards overlapping with the range are locked one by one, so it
+    /// is not atomic. For `Partition::Hash`, all shards are locked during the scan to merge them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range `start > end`, or if range `start == end` and both bounds are `Excluded`.
+    pub fn range<R, F>(&self, range: R, mut f: F)
+    where
+        R: RangeBounds<str>,
+        F: FnMut(&str, &V),
+    {
+        let bounds = (range.start_bound(), range.end_bound());
+        if self.partition == Partition::Hash {
+            let shards = self
+                .shards
+                .iter()
+                .map(|shard| shard.lock())
+                .collect::<Vec<_>>();
+            let mut iters = shards
+                .iter()
+                .map(|shard| shard.range::<str, _>(bounds))
+                .collect::<Vec<_>>();
+
+            // The smallest key of each shard not yet visited, with the index of the shard. The keys
+            // of different shards are different.
+            let mut heap = BinaryHeap::new();
+            let mut values = vec![None; iters.len()];
+            for (index, iter) in iters.iter_mut().enumerate() {
+                if let Some((key, value)) = iter.next() {
+                    heap.push(Reverse((key, index)));
+                    values[index] = Some(value);
+                }
+            }
+            while let Some(Reverse((key, index))) = heap.pop() {
+                f(&key, values[index].unwrap());
+                if let Some((key, value)) = iters[index].next() {
+                    heap.push(Reverse((key, index)));
+                    values[index] = Some(value);
+                }
+            }
+            return;
+        }
+
+        let first = match bounds.0 {
+            Bound::Included(key) | Bound::Excluded(key) => self.index(key),
+            Bound::Unbounded => 0,
+        };
+        let last = match bounds.1 {
+            Bound::Included(key) | Bound::Excluded(key) => self.index(key),
+            Bound::Unbounded => self.shards.len() - 1,
+        };
+        for shard in &self.shards[first..=last.max(first)] {
+            for (key, value) in shard.lock().range::<str, _>(bounds) {
+                f(&key, value);
+            }
+        }
+    }
+}
+
+impl<V, L: RawLock> Default for ShardedArt<V, L> {
+    fn default() -> Self {
+        Self::new(16, Partition::Prefix)
+    }
+}
+
+impl<V, L: RawLock> fmt::Debug for ShardedArt<V, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShardedArt")
+            .field("shards", &self.shards.len())
+            .field("partition", &self.partition)
+            .finish()
+    }
+}
+
+impl<V, L: RawLock> ConcurrentMap<V> for ShardedArt<V, L> {
+    fn insert<'a>(&'a self, key: &'a str, value: V, guard: &'a Guard) -> Result<(), V> {
+        self.shard(key).insert(key, value, guard)
+    }
+
+    fn delete(&self, key: &str, guard: &Guard) -> Result<V, ()> {
+        self.shard(key).delete(key, guard)
+    }
+
+    fn lookup<'a, F, R>(&'a self, key: &'a str, guard: &'a Guard, f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        self.shard(key).lookup(key, guard, f)
+    }
+
+    fn insert_or_replace(&self, key: &str, value: V, guard: &Guard) -> Option<V> {
+        self.shard(key).insert_or_replace(key, value, guard)
+    }
+
+    fn upsert<F>(&self, key: &str, f: F, guard: &Guard)
+    where
+        F: FnOnce(Option<&V>) -> V,
+    {
+        self.shard(key).upsert(key, f, guard)
+    }
+}
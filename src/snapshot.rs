@@ -0,0 +1,318 @@
+//! Binary snapshots of trees.
+//!
+//! A snapshot consists of the following, where integers are in little endian:
+//!
+//! - The magic number `b"ART\0"`, and the format version as `u32`, which is currently 1.
+//! - The root node. A node is written as its kind as `u8`, i.e., 0, 1, 2, or 3 for an internal node
+//!   of capacity 4, 16, 48, or 256, and 4 for a leaf; the length of its header key as `u8`, and the
+//!   header key. Then an internal node is followed by the number of its children as `u16`, and the
+//!   key and the node of each child in ascending order of keys; a leaf is followed by the length of
+//!   its encoded value as `u64`, and the encoded value.
+//! - The 64-bit FNV-1a hash of all the preceding bytes as `u64`.
+//!
+//! The keys are stored as they are in the tree, i.e., escaped and ending with `KEY_ENDMARK`.
+
+use core::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use either::Either;
+
+use crate::key::unescape;
+use crate::node::*;
+use crate::validate::validate_escape;
+
+/// The magic number of snapshots.
+const MAGIC: [u8; 4] = *b"ART\0";
+
+/// The current version of the snapshot format.
+const VERSION: u32 = 1;
+
+/// The kind of leaves.
+const KIND_LEAF: u8 = 4;
+
+/// The 64-bit FNV-1a hash.
+#[derive(Debug)]
+struct Checksum(u64);
+
+impl Checksum {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// A writer computing the checksum of the written bytes.
+#[derive(Debug)]
+struct Writer<'a, W: Write> {
+    inner: &'a mut W,
+    checksum: Checksum,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.checksum.update(bytes);
+        self.inner.write_all(bytes)
+    }
+}
+
+/// A reader computing the checksum of the read bytes.
+#[derive(Debug)]
+struct Reader<'a, R: Read> {
+    inner: &'a mut R,
+    checksum: Checksum,
+}
+
+impl<'a, R: Read> Reader<'a, R> {
+    fn read(&mut self, length: usize) -> io::Result<Vec<u8>> {
+        // Does not allocate `length` bytes upfront, which may be arbitrarily large.
+        let mut bytes = vec![];
+        (&mut *self.inner)
+            .take(length as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.checksum.update(&bytes);
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.read(2)?[..].try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read(4)?[..].try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.read(8)?[..].try_into().unwrap()))
+    }
+}
+
+/// Returns an error for malformed snapshots.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes a snapshot of the tree of `root`, where `encode` appends the encoding of a value to the
+/// given bytes.
+pub(crate) fn write<V, W, F>(root: &NodeBox<V>, writer: &mut W, mut encode: F) -> io::Result<()>
+where
+    W: Write,
+    F: FnMut(&V, &mut Vec<u8>),
+{
+    let mut writer = Writer {
+        inner: writer,
+        checksum: Checksum::new(),
+    };
+    writer.write(&MAGIC)?;
+    writer.write(&VERSION.to_le_bytes())?;
+    write_node(root, &mut writer, &mut encode, &mut vec![])?;
+    let checksum = writer.checksum.0;
+    writer.inner.write_all(&checksum.to_le_bytes())
+}
+
+fn write_node<V, W, F>(
+    node: &NodeBox<V>,
+    writer: &mut Writer<'_, W>,
+    encode: &mut F,
+    buffer: &mut Vec<u8>,
+) -> io::Result<()>
+where
+    W: Write,
+    F: FnMut(&V, &mut Vec<u8>),
+{
+    let (header, body) = node.deref().unwrap();
+    let kind = match body {
+        Either::Left(_) => match node.capacity() {
+            4 => 0,
+            16 => 1,
+            48 => 2,
+            _ => 3,
+        },
+        Either::Right(_) => KIND_LEAF,
+    };
+    writer.write(&[kind, header.length()])?;
+    writer.write(header.key())?;
+
+    match body {
+        Either::Left(body) => {
+            let keys = body.keys();
+            writer.write(&(keys.len() as u16).to_le_bytes())?;
+            for key in keys {
+                writer.write(&[key])?;
+                write_node(body.lookup(key).unwrap().1, writer, encode, buffer)?;
+            }
+        }
+        Either::Right(value) => {
+            buffer.clear();
+            encode(value, buffer);
+            writer.write(&(buffer.len() as u64).to_le_bytes())?;
+            writer.write(buffer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a snapshot written by `write()`, and returns the root of the tree, where `decode` decodes
+/// a value from its encoding, and `is_key` checks if the unescaped bytes of a key are the encoding
+/// of a key.
+///
+/// Returns an error of kind `InvalidData` if the snapshot is malformed, or its version is not
+/// supported. A tree read successfully is valid as in `validate()`.
+pub(crate) fn read<V, R, F>(
+    reader: &mut R,
+    mut decode: F,
+    is_key: impl Fn(&[u8]) -> bool,
+) -> io::Result<NodeBox<V>>
+where
+    R: Read,
+    F: FnMut(&[u8]) -> io::Result<V>,
+{
+    let mut reader = Reader {
+        inner: reader,
+        checksum: Checksum::new(),
+    };
+    if reader.read(MAGIC.len())? != MAGIC {
+        return Err(invalid("invalid magic number of snapshot"));
+    }
+    let version = reader.read_u32()?;
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "unsupported snapshot version {}",
+            version
+        )));
+    }
+
+    let root = read_nodes(&mut reader, &mut decode, is_key)?;
+    if root.deref().unwrap().0.length() != 0 || root.capacity() != 256 {
+        return Err(invalid("invalid root of snapshot"));
+    }
+
+    let checksum = reader.checksum.0;
+    if reader.read_u64()? != checksum {
+        return Err(invalid("checksum mismatch of snapshot"));
+    }
+    Ok(root)
+}
+
+/// An internal node being read, whose children are not all read yet.
+#[derive(Debug)]
+struct Frame<V> {
+    header: NodeHeader,
+    capacity: usize,
+    /// The number of children.
+    length: usize,
+    /// The key of the child being read.
+    key: u8,
+    children: Vec<(u8, NodeBox<V>)>,
+}
+
+/// Reads the nodes in pre-order, and returns the root.
+///
+/// The ancestors of the node being read are kept in an explicit stack instead of recursion, so that
+/// a deep tree in a malformed snapshot does not overflow the call stack.
+fn read_nodes<V, R, F>(
+    reader: &mut Reader<'_, R>,
+    decode: &mut F,
+    is_key: impl Fn(&[u8]) -> bool,
+) -> io::Result<NodeBox<V>>
+where
+    R: Read,
+    F: FnMut(&[u8]) -> io::Result<V>,
+{
+    let mut stack = Vec::<Frame<V>>::new();
+    // The concatenation of the header keys of the nodes in `stack`.
+    let mut prefix = vec![];
+
+    loop {
+        let root = stack.is_empty();
+        let kind = reader.read_u8()?;
+        let length = reader.read_u8()?;
+        let key = reader.read(usize::from(length))?;
+        let header = NodeHeader::new(&key).map_err(|_| invalid("too long header of snapshot"))?;
+        if !root && key.is_empty() {
+            return Err(invalid("empty header of snapshot"));
+        }
+
+        // Every key ends with `KEY_ENDMARK`, which appears nowhere else.
+        let capacity = match kind {
+            0 => 4,
+            1 => 16,
+            2 => 48,
+            3 => 256,
+            KIND_LEAF => 0,
+            _ => return Err(invalid("invalid node kind of snapshot")),
+        };
+        let mut node = if kind == KIND_LEAF {
+            if key.last() != Some(&KEY_ENDMARK) || key[..key.len() - 1].contains(&KEY_ENDMARK) {
+                return Err(invalid("invalid leaf of snapshot"));
+            }
+            let length = prefix.len();
+            prefix.extend_from_slice(&key[..key.len() - 1]);
+            let valid = validate_escape(prefix.iter()).is_ok() && is_key(&unescape(&prefix));
+            prefix.truncate(length);
+            if !valid {
+                return Err(invalid("invalid key of snapshot"));
+            }
+
+            let length = reader.read_u64()?;
+            let length = length
+                .try_into()
+                .map_err(|_| invalid("too long value of snapshot"))?;
+            let value = decode(&reader.read(length)?)?;
+            NodeBox::newv(header, value)
+        } else {
+            if key.contains(&KEY_ENDMARK) {
+                return Err(invalid("invalid internal node of snapshot"));
+            }
+            let length = usize::from(reader.read_u16()?);
+            if length > capacity || (!root && length == 0) {
+                return Err(invalid("invalid number of children of snapshot"));
+            }
+            if length != 0 {
+                prefix.extend_from_slice(&key);
+                stack.push(Frame {
+                    header,
+                    capacity,
+                    length,
+                    key: reader.read_u8()?,
+                    children: Vec::with_capacity(length),
+                });
+                continue;
+            }
+            NodeBox::newi(header, vec![], capacity)
+        };
+
+        // Adds the node to its parent, and finishes the parents whose children are all read.
+        loop {
+            let parent = some_or!(stack.last_mut(), return Ok(node));
+            let unsorted = matches!(parent.children.last(), Some((k, _)) if *k >= parent.key);
+            if node.deref().unwrap().0.key()[0] != parent.key || unsorted {
+                return Err(invalid("invalid child of snapshot"));
+            }
+            parent.children.push((parent.key, node));
+            if parent.children.len() < parent.length {
+                parent.key = reader.read_u8()?;
+                break;
+            }
+
+            let parent = stack.pop().unwrap();
+            prefix.truncate(prefix.len() - usize::from(parent.header.length()));
+            let length = parent.children.len();
+            node = NodeBox::newi(parent.header, parent.children, parent.capacity);
+            if !stack.is_empty() && length <= node.shrink_threshold() {
+                return Err(invalid("too few children of snapshot"));
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use either::Either;
+
+use crate::node::*;
+
+/// Statistics of the nodes of a kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NodeStats {
+    /// The number of nodes.
+    pub nodes: usize,
+    /// The total number of children of the nodes, which is zero for leaves.
+    pub children: usize,
+    /// The total number of slots for children of the nodes, which is zero for leaves.
+    pub slots: usize,
+    /// The total length of the header keys of the nodes.
+    pub header_bytes: usize,
+    /// The total size of the nodes in bytes, including the padding of `CachePadded`.
+    pub bytes: usize,
+}
+
+impl NodeStats {
+    /// Returns the ratio of the occupied slots for children, or `None` if there is no slot.
+    pub fn fill_factor(&self) -> Option<f64> {
+        if self.slots == 0 {
+            None
+        } else {
+            Some(self.children as f64 / self.slots as f64)
+        }
+    }
+
+    /// Returns the ratio of the used bytes of the header keys, or `None` if there is no node.
+    pub fn header_usage(&self) -> Option<f64> {
+        if self.nodes == 0 {
+            None
+        } else {
+            Some(self.header_bytes as f64 / (self.nodes * NodeHeader::MAX_LENGTH) as f64)
+        }
+    }
+
+    fn add<V>(&mut self, node: &NodeBox<V>, children: usize, slots: usize) {
+        self.nodes += 1;
+        self.children += children;
+        self.slots += slots;
+        self.header_bytes += usize::from(node.deref().unwrap().0.length());
+        self.bytes += node.size();
+    }
+}
+
+/// Statistics of the shape and the memory usage of a tree, returned by `Art::stats()`.
+///
+/// The memory owned by values, e.g., the buffer of a `String`, is not counted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// The statistics of internal nodes of capacity 4.
+    pub node4: NodeStats,
+    /// The statistics of internal nodes of capacity 16.
+    pub node16: NodeStats,
+    /// The statistics of internal nodes of capacity 48.
+    pub node48: NodeStats,
+    /// The statistics of internal nodes of capacity 256, including the root.
+    pub node256: NodeStats,
+    /// The statistics of leaves.
+    pub leaves: NodeStats,
+    /// The number of leaves at each depth, where the root is at depth 0.
+    pub depths: Vec<usize>,
+}
+
+impl Stats {
+    /// Returns the statistics of all the nodes.
+    pub fn total(&self) -> NodeStats {
+        let mut result = NodeStats::default();
+        for stats in &[
+            self.node4,
+            self.node16,
+            self.node48,
+            self.node256,
+            self.leaves,
+        ] {
+            result.nodes += stats.nodes;
+            result.children += stats.children;
+            result.slots += stats.slots;
+            result.header_bytes += stats.header_bytes;
+            result.bytes += stats.bytes;
+        }
+        result
+    }
+
+    /// Returns the average depth of leaves, or `None` if there is no leaf.
+    pub fn average_depth(&self) -> Option<f64> {
+        if self.leaves.nodes == 0 {
+            None
+        } else {
+            let sum = self
+                .depths
+                .iter()
+                .enumerate()
+                .map(|(depth, count)| depth * count)
+                .sum::<usize>();
+            Some(sum as f64 / self.leaves.nodes as f64)
+        }
+    }
+}
+
+/// Collects the statistics of the tree of `root`.
+pub(crate) fn collect<V>(root: &NodeBox<V>) -> Stats {
+    let mut stats = Stats::default();
+    collect_node(root, 0, &mut stats);
+    stats
+}
+
+fn collect_node<V>(node: &NodeBox<V>, depth: usize, stats: &mut Stats) {
+    let body = match node.deref().unwrap().1 {
+        Either::Left(body) => body,
+        Either::Right(_) => {
+            stats.leaves.add(node, 0, 0);
+            if stats.depths.len() <= depth {
+                stats.depths.resize(depth + 1, 0);
+            }
+            stats.depths[depth] += 1;
+            return;
+        }
+    };
+
+    let keys = body.keys();
+    let capacity = node.capacity();
+    let kind = match capacity {
+        4 => &mut stats.node4,
+        16 => &mut stats.node16,
+        48 => &mut stats.node48,
+        _ => &mut stats.node256,
+    };
+    kind.add(node, keys.len(), capacity);
+    for key in keys {
+        collect_node(body.lookup(key).unwrap().1, depth + 1, stats);
+    }
+}
@@ -0,0 +1,130 @@
+use core::fmt;
+use std::error::Error;
+
+use either::Either;
+
+use crate::key::KEY_ESCAPE;
+use crate::node::*;
+
+/// An error returned by `Art::validate()`, describing a violated invariant of a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The keys of the edges from the root to the invalid node.
+    pub path: Vec<u8>,
+    /// The description of the violated invariant.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "root")?;
+        for key in &self.path {
+            write!(f, " -> {:#04x}", key)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Checks the invariants of the tree of `root`.
+pub(crate) fn validate<V>(root: &NodeBox<V>) -> Result<(), ValidationError> {
+    let mut path = vec![];
+    validate_node(root, &mut path, &mut vec![]).map_err(|message| ValidationError { path, message })
+}
+
+/// Checks the invariants of the subtree of `node`, where `path` is the keys of the edges to the
+/// node and `key` is the concatenation of the header keys of its ancestors.
+///
+/// On error, `path` is left as the path to the invalid node.
+fn validate_node<V>(
+    node: &NodeBox<V>,
+    path: &mut Vec<u8>,
+    key: &mut Vec<u8>,
+) -> Result<(), String> {
+    node.validate()?;
+    let (header, body) = node.deref().unwrap();
+    let root = path.is_empty();
+    if root {
+        if header.length() != 0 || body.is_right() || node.capacity() != 256 {
+            return Err(
+                "the root is not an internal node of capacity 256 with an empty header".to_string(),
+            );
+        }
+    } else if header.key().first() != path.last() {
+        return Err(format!(
+            "header {:02x?} does not start with the key of the edge",
+            header.key()
+        ));
+    }
+
+    let body = match body {
+        Either::Left(body) => body,
+        Either::Right(_) => {
+            let (last, rest) = header.key().split_last().unwrap();
+            if *last != KEY_ENDMARK || rest.contains(&KEY_ENDMARK) {
+                return Err(format!(
+                    "leaf header {:02x?} does not end the key",
+                    header.key()
+                ));
+            }
+            return validate_escape(key.iter().chain(rest));
+        }
+    };
+
+    if header.key().contains(&KEY_ENDMARK) {
+        return Err(format!(
+            "internal header {:02x?} contains the end mark",
+            header.key()
+        ));
+    }
+    let keys = body.keys();
+    if !root {
+        if keys.is_empty() {
+            return Err("empty internal node".to_string());
+        }
+        if keys.len() <= node.shrink_threshold() {
+            return Err(format!(
+                "internal node of capacity {} has only {} children",
+                node.capacity(),
+                keys.len()
+            ));
+        }
+    }
+
+    let length = key.len();
+    key.extend_from_slice(header.key());
+    let mut count = 0;
+    for k in keys {
+        let (_, child) = body.lookup(k).unwrap();
+        path.push(k);
+        validate_node(child, path, key)?;
+        path.pop();
+        count += child.count();
+    }
+    key.truncate(length);
+
+    if header.count() != count {
+        return Err(format!(
+            "count {} differs from the number of leaves {}",
+            header.count(),
+            count
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that an escape byte in a stored key is followed by `0` or `1`.
+pub(crate) fn validate_escape<'a>(key: impl Iterator<Item = &'a u8>) -> Result<(), String> {
+    let mut escaped = false;
+    for b in key {
+        if escaped && *b > 1 {
+            return Err(format!("escape byte followed by {:#04x}", b));
+        }
+        escaped = !escaped && *b == KEY_ESCAPE;
+    }
+    if escaped {
+        return Err("key ends with an escape byte".to_string());
+    }
+    Ok(())
+}
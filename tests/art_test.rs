@@ -1,8 +1,17 @@
 use rand::distributions::Alphanumeric;
 use rand::prelude::*;
 
-use cs492_concur_art::{Art, SequentialMap};
-use std::collections::HashMap;
+use crossbeam_epoch::pin;
+use cs492_concur_art::{
+    Art, ArtKey, ConcurrentMap, Entry, LeftRight, OlcArt, Partition, PlcArt, RowexArt, RwLock,
+    SequentialMap, ShardedArt, SpinRwLock, ValidationError,
+};
+use lock::{Lock, SpinLock, TicketLock};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::io;
+use std::ops::Bound;
+use std::panic::{self, AssertUnwindSafe};
 
 #[derive(Debug)]
 enum Ops {
@@ -62,7 +71,8 @@ fn regression_delete_after_enlarge() {
     assert!(art.insert("A", 5).is_ok());
     assert!(art.delete("A").is_ok());
     assert_eq!(art.lookup("A"), None);
-    
+    art.validate().unwrap();
+
     assert_eq!(art.lookup("AB"), Some(&4));
     assert!(art.delete("AB").is_ok());
     assert_eq!(art.lookup("AB"), None);
@@ -78,6 +88,7 @@ fn regression_delete_after_enlarge() {
     assert_eq!(art.lookup("ABCDE"), Some(&1));
     assert!(art.delete("ABCDE").is_ok());
     assert_eq!(art.lookup("ABCDE"), None);
+    art.validate().unwrap();
 }
 
 #[test]
@@ -132,3 +143,1105 @@ fn stress() {
         }
     }
 }
+
+#[test]
+fn iter_sorted() {
+    let keys = ["b", "a", "ab", "abc", "", "ba", "abd", "aa", "b0"];
+    let mut art = Art::new();
+    let mut btree = BTreeMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        assert!(art.insert(key, i).is_ok());
+        btree.insert(key.to_string(), i);
+    }
+    assert!(art.delete("ab").is_ok());
+    btree.remove("ab");
+
+    assert!(art
+        .iter()
+        .map(|(k, v)| (k, *v))
+        .eq(btree.clone().into_iter()));
+    assert!(art.keys().rev().eq(btree.keys().rev().cloned()));
+    assert!(art.values().eq(btree.values()));
+
+    let mut iter = art.iter();
+    assert_eq!(iter.next(), Some(("".to_string(), &4)));
+    assert_eq!(iter.next_back(), Some(("ba".to_string(), &5)));
+    assert_eq!(iter.count(), btree.len() - 2);
+
+    for (_, v) in art.iter_mut() {
+        *v += 10;
+    }
+    assert!(art
+        .into_iter()
+        .eq(btree.into_iter().map(|(k, v)| (k, v + 10))));
+}
+
+#[test]
+fn range() {
+    let keys = ["", "a", "aa", "ab", "abc", "abd", "b", "ba", "bb", "c"];
+    let mut art = Art::new();
+    let mut btree = BTreeMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        assert!(art.insert(key, i).is_ok());
+        btree.insert(key.to_string(), i);
+    }
+
+    let bounds = ["", "a", "ab", "abb", "abc", "b", "bz", "d"];
+    for start in bounds.iter() {
+        for end in bounds.iter().filter(|end| start <= *end) {
+            let expected = btree
+                .range::<str, _>((Bound::Included(*start), Bound::Excluded(*end)))
+                .map(|(k, v)| (k.clone(), v));
+            assert!(art
+                .range((Bound::Included(*start), Bound::Excluded(*end)))
+                .eq(expected));
+
+            let expected = btree
+                .range::<str, _>((Bound::Excluded(*start), Bound::Included(*end)))
+                .rev()
+                .map(|(k, v)| (k.clone(), v));
+            assert!(art
+                .range((Bound::Excluded(*start), Bound::Included(*end)))
+                .rev()
+                .eq(expected));
+        }
+    }
+
+    for (_, v) in art.range_mut((Bound::Included("ab"), Bound::Unbounded)) {
+        *v += 100;
+    }
+    assert_eq!(art.lookup("aa"), Some(&2));
+    assert_eq!(art.lookup("ab"), Some(&103));
+    assert_eq!(art.lookup("c"), Some(&109));
+}
+
+#[test]
+fn prefix_iter() {
+    let keys = ["", "a", "ab", "abcde", "abcdf", "abd", "b", "ba"];
+    let mut art = Art::new();
+    for (i, key) in keys.iter().enumerate() {
+        assert!(art.insert(key, i).is_ok());
+    }
+
+    let prefixes = [
+        "", "a", "ab", "abc", "abcd", "abcde", "abcdg", "abx", "b", "c",
+    ];
+    for prefix in prefixes.iter() {
+        let expected = keys.iter().filter(|k| k.starts_with(prefix)).cloned();
+        assert!(art
+            .prefix_iter(*prefix)
+            .map(|(k, _)| k)
+            .eq(expected.clone()));
+        assert!(art
+            .prefix_iter(*prefix)
+            .rev()
+            .map(|(k, _)| k)
+            .eq(expected.rev()));
+    }
+}
+
+#[test]
+fn delete_shrink() {
+    let mut rng = thread_rng();
+    let mut keys = (0..1000)
+        .map(|i| format!("{}{:04}", "P".repeat(i % 60), i * 37 % 1000))
+        .collect::<Vec<_>>();
+    let mut art = Art::new();
+    for (i, key) in keys.iter().enumerate() {
+        assert!(art.insert(key, i).is_ok());
+    }
+
+    keys.shuffle(&mut rng);
+    let (deleted, kept) = keys.split_at(900);
+    for key in deleted {
+        assert!(art.delete(key).is_ok());
+    }
+    for key in deleted {
+        assert_eq!(art.lookup(key), None);
+    }
+    art.validate().unwrap();
+
+    let mut kept = kept.to_vec();
+    kept.sort();
+    assert!(art.keys().eq(kept.iter().cloned()));
+    for key in kept.iter() {
+        assert!(art.delete(key).is_ok());
+    }
+    assert_eq!(art.iter().next(), None);
+}
+
+#[test]
+fn generic_keys() {
+    let mut ints = Art::<_, i64>::default();
+    let keys = [0, -1, 1, i64::MIN, i64::MAX, 255, 256, -256];
+    for key in keys.iter() {
+        assert!(ints.try_insert(key, *key).is_ok());
+    }
+    assert!(ints.try_insert(&-1, 0).is_err());
+    let mut sorted = keys.to_vec();
+    sorted.sort();
+    assert!(ints.keys().eq(sorted.iter().cloned()));
+    assert!(ints
+        .range(-256..=1)
+        .map(|(k, _)| k)
+        .eq(vec![-256, -1, 0, 1]));
+    assert_eq!(ints.remove(&i64::MIN), Some(i64::MIN));
+    assert!(!ints.contains_key(&i64::MIN));
+
+    let mut pairs = Art::<_, (String, u32)>::default();
+    let keys = [("a", 2), ("a", 10), ("ab", 1), ("", 7), ("b", 0)];
+    for (i, (tenant, id)) in keys.iter().enumerate() {
+        assert!(pairs.try_insert(&(tenant.to_string(), *id), i).is_ok());
+    }
+    let mut sorted = keys
+        .iter()
+        .map(|(t, id)| (t.to_string(), *id))
+        .collect::<Vec<_>>();
+    sorted.sort();
+    assert!(pairs.keys().eq(sorted.iter().cloned()));
+    assert_eq!(pairs.get(&("a".to_string(), 10)), Some(&1));
+    let mut prefix = vec![];
+    "a".encode_nested(&mut prefix);
+    assert!(pairs
+        .prefix_iter(prefix.as_slice())
+        .map(|(k, _)| k)
+        .eq(vec![("a".to_string(), 2), ("a".to_string(), 10)]));
+
+    let mut bytes = Art::<_, [u8]>::default();
+    let keys: [&[u8]; 6] = [b"", b"\x00", b"\xfd", b"\xfe", b"\xff", b"\xff\xff"];
+    for (i, key) in keys.iter().enumerate() {
+        assert!(bytes.try_insert(*key, i).is_ok());
+    }
+    assert!(bytes
+        .iter()
+        .map(|(k, _)| k)
+        .eq(keys.iter().map(|k| k.to_vec())));
+    for key in keys.iter() {
+        assert!(bytes.remove(*key).is_some());
+    }
+    assert_eq!(bytes.iter().next(), None);
+}
+
+#[test]
+fn binary_keys() {
+    let mut rng = thread_rng();
+    let mut art = Art::<_, [u8]>::default();
+    let mut map = BTreeMap::new();
+    for i in 0..10_000 {
+        let len = rng.gen_range(0, 6);
+        let key = (0..len)
+            .map(|_| *[0x00, 0x01, 0xfd, 0xfe, 0xff].choose(&mut rng).unwrap())
+            .collect::<Vec<u8>>();
+        if rng.gen() {
+            let inserted = !map.contains_key(&key);
+            if inserted {
+                map.insert(key.clone(), i);
+            }
+            assert_eq!(art.try_insert(&key, i).is_ok(), inserted);
+        } else {
+            assert_eq!(art.remove(&key), map.remove(&key));
+        }
+    }
+
+    for (key, value) in map.iter() {
+        assert_eq!(art.get(key), Some(value));
+    }
+    assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+    assert!(art
+        .prefix_iter(&[0xfe, 0xff][..])
+        .map(|(k, _)| k)
+        .eq(map.keys().filter(|k| k.starts_with(&[0xfe, 0xff])).cloned()));
+}
+
+#[test]
+fn entry_api() {
+    let mut art = Art::new();
+    match art.entry("a") {
+        Entry::Vacant(entry) => {
+            assert_eq!(entry.key(), "a");
+            *entry.insert(1) += 1;
+        }
+        Entry::Occupied(_) => panic!("entry should be vacant"),
+    }
+    match art.entry("a") {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), "a");
+            assert_eq!(*entry.get(), 2);
+            assert_eq!(entry.insert(3), 2);
+            *entry.get_mut() += 1;
+            assert_eq!(*entry.into_mut(), 4);
+        }
+        Entry::Vacant(_) => panic!("entry should be occupied"),
+    }
+
+    assert_eq!(*art.entry("ab").or_insert(5), 5);
+    assert_eq!(*art.entry("ab").or_insert(6), 5);
+    assert_eq!(*art.entry("abc").or_insert_with_key(|k| k.len()), 3);
+    assert_eq!(*art.entry("b").or_default(), 0);
+    assert_eq!(*art.entry("b").and_modify(|v| *v += 7).or_default(), 7);
+    assert_eq!(*art.entry("c").and_modify(|v| *v += 7).or_default(), 0);
+    assert_eq!(art.entry("abc").key(), "abc");
+
+    match art.entry("ab") {
+        Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), ("ab".to_string(), 5)),
+        Entry::Vacant(_) => panic!("entry should be occupied"),
+    }
+    assert_eq!(art.lookup("ab"), None);
+    assert!(art
+        .iter()
+        .map(|(k, v)| (k, *v))
+        .eq(vec![("a", 4), ("abc", 3), ("b", 7), ("c", 0)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))));
+}
+
+#[test]
+fn insert_or_replace() {
+    let mut art = Art::new();
+    assert_eq!(art.insert_or_replace("a", 1), None);
+    assert_eq!(art.insert_or_replace("a", 2), Some(1));
+    assert_eq!(*art.upsert("a", |v| v.map_or(0, |v| v + 10)), 12);
+    assert_eq!(*art.upsert("b", |v| v.map_or(0, |v| v + 10)), 0);
+    assert_eq!(SequentialMap::insert_or_replace(&mut art, "b", 3), Some(0));
+    assert_eq!(art.lookup("a"), Some(&12));
+    assert_eq!(art.lookup("b"), Some(&3));
+
+    let map = Lock::<SpinLock, Art<usize>>::new(Art::new());
+    let guard = pin();
+    assert_eq!(map.insert_or_replace("a", 1, &guard), None);
+    assert_eq!(map.insert_or_replace("a", 2, &guard), Some(1));
+    map.upsert("a", |v| v.unwrap() + 1, &guard);
+    map.upsert("b", |v| v.map_or(5, |v| v + 1), &guard);
+    assert_eq!(map.lookup("a", &guard, |v| v.cloned()), Some(3));
+    assert_eq!(map.lookup("b", &guard, |v| v.cloned()), Some(5));
+}
+
+#[test]
+fn len_and_counts() {
+    let mut rng = thread_rng();
+    let mut art = Art::new();
+    let mut map = BTreeMap::new();
+    assert!(art.is_empty());
+    for _ in 0..10_000 {
+        let key = generate_random_string(&mut rng);
+        if rng.gen_range(0, 3) == 0 {
+            assert_eq!(art.delete(&key).ok(), map.remove(&key));
+        } else {
+            assert_eq!(art.insert(&key, ()).is_ok(), map.insert(key, ()).is_none());
+        }
+        assert_eq!(art.len(), map.len());
+    }
+    assert_eq!(art.is_empty(), map.is_empty());
+
+    for _ in 0..100 {
+        let prefix = generate_random_string(&mut rng);
+        let prefix = &prefix[0..prefix.len() % 3];
+        let expected = map.keys().filter(|k| k.starts_with(prefix)).count();
+        assert_eq!(art.count_prefix(prefix), expected);
+        assert_eq!(art.prefix_iter(prefix).len(), expected);
+
+        let mut start = generate_random_string(&mut rng);
+        let mut end = generate_random_string(&mut rng);
+        if start > end {
+            std::mem::swap(&mut start, &mut end);
+        }
+        let range = (
+            Bound::Excluded(start.as_str()),
+            Bound::Included(end.as_str()),
+        );
+        let count = map.range::<str, _>(range).count();
+        assert_eq!(art.count_range::<str, _>(range), count);
+        let mut iter = art.range::<str, _>(range);
+        assert_eq!(iter.size_hint(), (count, Some(count)));
+        if iter.next_back().is_some() {
+            assert_eq!(iter.size_hint(), (count - 1, Some(count - 1)));
+        }
+        if start != end {
+            let range = (
+                Bound::Included(start.as_str()),
+                Bound::Excluded(end.as_str()),
+            );
+            assert_eq!(
+                art.count_range::<str, _>(range),
+                map.range::<str, _>(range).count()
+            );
+        }
+    }
+
+    let mut ints = Art::<_, u32>::default();
+    for i in 0..1000 {
+        assert!(ints.try_insert(&(i * 3), ()).is_ok());
+    }
+    assert_eq!(ints.count_range(10..=300), 97);
+    assert_eq!(ints.count_range(..), 1000);
+
+    let mut iter = art.iter();
+    let _ = iter.next();
+    let _ = iter.next_back();
+    assert_eq!(iter.len(), map.len() - 2);
+    assert_eq!(art.keys().len(), map.len());
+    assert_eq!(art.values().rev().skip(3).len(), map.len() - 3);
+
+    // As in `std`, the iterators can be sent to other threads.
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    assert_send_sync(&art.iter());
+    assert_send_sync(&art.range::<str, _>(..));
+    assert_send_sync(&art.keys());
+    assert_send_sync(&art.values());
+    assert_send_sync(&art.iter_mut());
+    assert_send_sync(&art.range_mut::<str, _>(..));
+    let iter = ints.iter();
+    let count = crossbeam_utils::thread::scope(|s| s.spawn(move |_| iter.count()).join().unwrap());
+    assert_eq!(count.unwrap(), 1000);
+}
+
+#[test]
+fn first_last() {
+    let mut rng = thread_rng();
+    let mut art = Art::<_, u64>::default();
+    let mut map = BTreeMap::new();
+    assert_eq!(art.first(), None);
+    assert_eq!(art.pop_last(), None);
+    for i in 0..10_000 {
+        let deadline = rng.gen_range(0, 100_000);
+        if art.try_insert(&deadline, i).is_ok() {
+            assert!(map.insert(deadline, i).is_none());
+        }
+        assert_eq!(art.first(), map.iter().next().map(|(k, v)| (*k, v)));
+        assert_eq!(art.last(), map.iter().next_back().map(|(k, v)| (*k, v)));
+    }
+
+    while !map.is_empty() {
+        if rng.gen() {
+            let (k, v) = map.iter().next().map(|(k, v)| (*k, *v)).unwrap();
+            assert_eq!(art.pop_first(), map.remove(&k).map(|_| (k, v)));
+        } else {
+            let (k, v) = map.iter().next_back().map(|(k, v)| (*k, *v)).unwrap();
+            assert_eq!(art.pop_last(), map.remove(&k).map(|_| (k, v)));
+        }
+    }
+    assert_eq!(art.pop_first(), None);
+    assert!(art.is_empty());
+
+    let mut art = Art::new();
+    for key in ["", "a", "ab", "b"].iter() {
+        assert!(art.insert(key, key.len()).is_ok());
+    }
+    assert_eq!(art.first(), Some(("".to_string(), &0)));
+    assert_eq!(art.last(), Some(("b".to_string(), &1)));
+    assert_eq!(art.pop_first(), Some(("".to_string(), 0)));
+    assert_eq!(art.pop_last(), Some(("b".to_string(), 1)));
+    assert_eq!(art.pop_last(), Some(("ab".to_string(), 2)));
+}
+
+#[test]
+fn floor_ceiling() {
+    let mut rng = thread_rng();
+    let mut art = Art::<_, i32>::default();
+    let mut map = BTreeMap::new();
+    for i in 0..1000 {
+        let key = rng.gen_range(-10_000, 10_000);
+        if art.try_insert(&key, i).is_ok() {
+            assert!(map.insert(key, i).is_none());
+        }
+    }
+
+    for _ in 0..1000 {
+        let key = rng.gen_range(-11_000, 11_000);
+        let entry = |(k, v): (&i32, &usize)| (*k, *v);
+        let value = |(k, v): (i32, &usize)| (k, *v);
+        assert_eq!(
+            art.floor(&key).map(value),
+            map.range(..=key).next_back().map(entry)
+        );
+        assert_eq!(
+            art.ceiling(&key).map(value),
+            map.range(key..).next().map(entry)
+        );
+        assert_eq!(
+            art.lower_bound(Bound::Excluded(&key)).map(value),
+            map.range((Bound::Excluded(key), Bound::Unbounded))
+                .next()
+                .map(entry)
+        );
+        assert_eq!(
+            art.upper_bound(Bound::Excluded(&key)).map(value),
+            map.range(..key).next_back().map(entry)
+        );
+    }
+    assert_eq!(
+        art.lower_bound::<i32>(Bound::Unbounded).map(|(k, _)| k),
+        map.keys().next().cloned()
+    );
+}
+
+#[test]
+fn longest_prefix_match() {
+    let mut art = Art::new();
+    let routes = [
+        "",
+        "/api",
+        "/api/v1",
+        "/api/v1/users",
+        "/static",
+        "/static/css/",
+    ];
+    for (i, route) in routes.iter().enumerate() {
+        assert!(art.insert(route, i).is_ok());
+    }
+
+    let queries = [
+        "",
+        "/",
+        "/api",
+        "/api/",
+        "/api/v1/user",
+        "/api/v1/users",
+        "/api/v1/users/42",
+        "/static/css",
+        "/static/css/main.css",
+        "/statics",
+    ];
+    for query in queries.iter() {
+        let expected = routes
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| query.starts_with(*r))
+            .max_by_key(|(_, r)| r.len())
+            .map(|(i, r)| (r.to_string(), i));
+        assert_eq!(
+            art.longest_prefix_match(*query).map(|(k, v)| (k, *v)),
+            expected
+        );
+    }
+
+    assert!(art.delete("").is_ok());
+    assert_eq!(art.longest_prefix_match("/ap"), None);
+}
+
+#[test]
+fn std_traits() {
+    let entries = [("b", 2), ("a", 1), ("ab", 3), ("a", 4)];
+    let art = entries.iter().cloned().collect::<Art<_>>();
+    assert_eq!(art.len(), 3);
+    assert_eq!(art["a"], 4);
+    assert_eq!(art["ab"], 3);
+    assert_eq!(format!("{:?}", art), r#"{"a": 4, "ab": 3, "b": 2}"#);
+
+    let mut clone = art.clone();
+    assert_eq!(clone, art);
+    assert_eq!(clone.insert_or_replace("a", 1), Some(4));
+    assert_ne!(clone, art);
+    assert_eq!(art["a"], 4);
+    clone.extend(vec![("a".to_string(), 4)]);
+    assert_eq!(clone, art);
+
+    let mut hashes = HashMap::new();
+    hashes.insert(art.clone(), 1);
+    assert_eq!(hashes.get(&clone), Some(&1));
+
+    let ints = (0..1000u32).map(|i| (i, i * i)).collect::<Art<_, u32>>();
+    assert_eq!(ints[&31], 961);
+    assert_eq!(ints.clone(), ints);
+    assert!(ints.clone().into_iter().eq((0..1000).map(|i| (i, i * i))));
+}
+
+#[test]
+#[should_panic]
+fn index_missing() {
+    let art = vec![("a", 1)].into_iter().collect::<Art<_>>();
+    let _ = art["b"];
+}
+
+#[test]
+fn from_sorted_iter() {
+    let mut rng = thread_rng();
+    let mut map = BTreeMap::new();
+    for i in 0..10_000 {
+        let key = format!(
+            "{}{}",
+            "P".repeat(rng.gen_range(0, 60)),
+            generate_random_string(&mut rng)
+        );
+        let _ = map.insert(key, i);
+    }
+
+    let mut art = Art::<_>::from_sorted_iter(map.iter().map(|(k, v)| (k.as_str(), *v)));
+    art.validate().unwrap();
+    assert_eq!(art.len(), map.len());
+    assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+    assert_eq!(art, map.iter().map(|(k, v)| (k.as_str(), *v)).collect());
+
+    for (i, (key, value)) in map.iter().enumerate() {
+        if i % 2 == 0 {
+            assert_eq!(art.delete(key), Ok(*value));
+        } else {
+            assert_eq!(art.insert(key, 0).err().map(|(v, _)| *v), Some(*value));
+        }
+    }
+    assert_eq!(art.len(), map.len() / 2);
+    art.validate().unwrap();
+
+    let ints = Art::<_, u64>::from_sorted_iter(vec![(1, 'a'), (2, 'b'), (2, 'c'), (300, 'd')]);
+    assert!(ints.into_iter().eq(vec![(1, 'a'), (2, 'c'), (300, 'd')]));
+    assert!(Art::<(), u8>::from_sorted_iter(Vec::<(u8, ())>::new()).is_empty());
+}
+
+#[test]
+#[should_panic]
+fn from_sorted_iter_unsorted() {
+    let _ = Art::<_, str>::from_sorted_iter(vec![("b", 1), ("a", 2)]);
+}
+
+#[test]
+fn split_off_append() {
+    let mut rng = thread_rng();
+    let generate_key = |rng: &mut ThreadRng| {
+        let length = rng.gen_range(0, 8);
+        let key = (0..length)
+            .map(|_| *[b'a', b'b', b'c'].choose(rng).unwrap() as char)
+            .collect::<String>();
+        format!("{}{}", "P".repeat(rng.gen_range(0, 3) * 20), key)
+    };
+
+    for _ in 0..100 {
+        let mut art = Art::new();
+        let mut map = BTreeMap::new();
+        for i in 0..rng.gen_range(0, 500) {
+            let key = generate_key(&mut rng);
+            let _ = art.insert_or_replace(&key, i);
+            let _ = map.insert(key, i);
+        }
+
+        let key = generate_key(&mut rng);
+        let mut other = art.split_off(key.as_str());
+        let other_map = map.split_off(&key);
+        art.validate().unwrap();
+        other.validate().unwrap();
+        assert_eq!(art.len(), map.len());
+        assert_eq!(other.len(), other_map.len());
+        assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+        assert!(other
+            .iter()
+            .map(|(k, v)| (k, *v))
+            .eq(other_map.clone().into_iter()));
+
+        // Overlapping keys in `other` overwrite those in `art`.
+        let mut extra = BTreeMap::new();
+        for i in 0..rng.gen_range(0, 100) {
+            let key = generate_key(&mut rng);
+            let _ = other.insert_or_replace(&key, 1000 + i);
+            let _ = extra.insert(key, 1000 + i);
+        }
+        map.extend(other_map);
+        map.extend(extra);
+        art.append(&mut other);
+        art.validate().unwrap();
+        assert!(other.is_empty());
+        assert_eq!(other.iter().next(), None);
+        assert_eq!(art.len(), map.len());
+        assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+        for prefix in ["", "a", "Pb", "PPPPPPPPPPPPPPPPPPPPa"].iter() {
+            let expected = map.keys().filter(|k| k.starts_with(prefix)).count();
+            assert_eq!(art.count_prefix(*prefix), expected);
+        }
+
+        for (key, value) in map.iter() {
+            assert_eq!(art.delete(key), Ok(*value));
+        }
+        assert!(art.is_empty());
+    }
+}
+
+#[test]
+fn retain_extract_if() {
+    let mut rng = thread_rng();
+    let mut art = Art::new();
+    let mut map = BTreeMap::new();
+    for i in 0..10_000 {
+        let key = format!(
+            "{}{}",
+            "P".repeat(rng.gen_range(0, 40)),
+            generate_random_string(&mut rng)
+        );
+        let _ = art.insert_or_replace(&key, i);
+        let _ = map.insert(key, i);
+    }
+
+    art.retain(|k, v| {
+        *v += 1;
+        k.len() % 3 != 0
+    });
+    map = map
+        .into_iter()
+        .filter(|(k, _)| k.len() % 3 != 0)
+        .map(|(k, v)| (k, v + 1))
+        .collect();
+    assert_eq!(art.len(), map.len());
+    assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+    art.validate().unwrap();
+
+    let extracted = art.extract_if(|_, v| *v % 2 == 0).collect::<Vec<_>>();
+    let expected = map
+        .iter()
+        .filter(|(_, v)| **v % 2 == 0)
+        .map(|(k, v)| (k.clone(), *v))
+        .collect::<Vec<_>>();
+    assert_eq!(extracted, expected);
+    map.retain(|_, v| *v % 2 != 0);
+    art.validate().unwrap();
+    assert_eq!(art.len(), map.len());
+    assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+
+    // The iterator is lazy, and the entries not visited are retained.
+    let mut visited = 0;
+    let first = art
+        .extract_if(|_, _| {
+            visited += 1;
+            visited % 2 == 0
+        })
+        .next();
+    assert_eq!(visited, 2);
+    let (key, value) = map.iter().nth(1).unwrap();
+    assert_eq!(first, Some((key.clone(), *value)));
+    let _ = map.remove(&first.unwrap().0);
+    art.validate().unwrap();
+    assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+
+    // Dropping the iterator halfway shrinks the nodes on its path.
+    let extracted = art
+        .extract_if(|k, _| k.len() % 2 == 0)
+        .take(1000)
+        .collect::<Vec<_>>();
+    for (key, _) in &extracted {
+        let _ = map.remove(key);
+    }
+    art.validate().unwrap();
+    assert_eq!(art.len(), map.len());
+    assert!(art.iter().map(|(k, v)| (k, *v)).eq(map.clone().into_iter()));
+
+    assert!(art.drain().eq(map.into_iter()));
+    assert!(art.is_empty());
+    assert!(art.insert("a", 0).is_ok());
+    art.retain(|_, _| false);
+    assert_eq!(art.iter().next(), None);
+}
+
+#[test]
+fn snapshot() {
+    let mut rng = thread_rng();
+    let mut art = Art::new();
+    for i in 0..10_000u64 {
+        let key = format!(
+            "{}{}",
+            "P".repeat(rng.gen_range(0, 40)),
+            generate_random_string(&mut rng)
+        );
+        let _ = art.insert_or_replace(&key, i);
+    }
+
+    let encode = |v: &u64, bytes: &mut Vec<u8>| bytes.extend_from_slice(&v.to_le_bytes());
+    let decode = |bytes: &[u8]| {
+        let bytes: [u8; 8] = bytes
+            .try_into()
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+        Ok(u64::from_le_bytes(bytes))
+    };
+    let mut snapshot = vec![];
+    art.write_snapshot(&mut snapshot, encode).unwrap();
+    assert_eq!(&snapshot[0..8], b"ART\0\x01\0\0\0");
+
+    let mut loaded = Art::read_snapshot(&mut snapshot.as_slice(), decode).unwrap();
+    loaded.validate().unwrap();
+    assert_eq!(loaded, art);
+    assert_eq!(loaded.len(), art.len());
+    let mut again = vec![];
+    loaded.write_snapshot(&mut again, encode).unwrap();
+    assert_eq!(again, snapshot);
+    assert!(loaded.insert("PPP", 0).is_ok() || loaded.delete("PPP").is_ok());
+
+    let mut corrupted = snapshot.clone();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] ^= 1;
+    assert!(Art::<u64>::read_snapshot(&mut corrupted.as_slice(), decode).is_err());
+
+    let mut checksum = snapshot.clone();
+    *checksum.last_mut().unwrap() ^= 1;
+    let error = Art::<u64>::read_snapshot(&mut checksum.as_slice(), decode).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let mut version = snapshot.clone();
+    version[4] = 2;
+    let error = Art::<u64>::read_snapshot(&mut version.as_slice(), decode).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let truncated = &snapshot[0..snapshot.len() - 1];
+    let error = Art::<u64>::read_snapshot(&mut &truncated[..], decode).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn stats() {
+    let stats = Art::<()>::new().stats();
+    assert_eq!(stats.node256.nodes, 1);
+    assert_eq!(stats.total().nodes, 1);
+    assert_eq!(stats.node256.fill_factor(), Some(0.0));
+    assert_eq!(stats.average_depth(), None);
+
+    let mut rng = thread_rng();
+    let mut art = Art::new();
+    for i in 0..10_000 {
+        let _ = art.insert(&generate_random_string(&mut rng), i);
+    }
+    let _ = art.insert(&"P".repeat(100), 0);
+
+    let stats = art.stats();
+    let total = stats.total();
+    assert_eq!(stats.leaves.nodes, art.len());
+    assert_eq!(stats.depths.iter().sum::<usize>(), art.len());
+    assert_eq!(stats.depths[0], 0);
+    assert!(stats.depths.len() > 100 / 23);
+    assert_eq!(total.nodes, total.children + 1);
+    assert!(total.bytes >= total.nodes * 64);
+    for kind in &[stats.node4, stats.node16, stats.node48, stats.node256] {
+        if let Some(fill_factor) = kind.fill_factor() {
+            assert!(fill_factor <= 1.0);
+        }
+    }
+    assert!(stats.leaves.header_usage().unwrap() > 0.0);
+}
+
+#[test]
+fn validate() {
+    let mut rng = thread_rng();
+    let mut art = Art::new();
+    let mut keys = vec![];
+    for i in 0..10_000 {
+        let key = generate_random_string(&mut rng);
+        if art.insert(&key, i).is_ok() {
+            keys.push(key);
+        }
+    }
+    art.validate().unwrap();
+    for key in keys.iter().step_by(2) {
+        assert!(art.delete(key).is_ok());
+    }
+    art.validate().unwrap();
+
+    // A snapshot of a node of capacity 256 with only two leaves is rejected as in `validate()`.
+    let mut snapshot = b"\x03\0\x01\0a\x03\x01a\x02\0".to_vec();
+    snapshot.extend_from_slice(b"b\x04\x02b\xff\0\0\0\0\0\0\0\0");
+    snapshot.extend_from_slice(b"c\x04\x02c\xff\0\0\0\0\0\0\0\0");
+    let error = Art::<()>::read_snapshot(&mut seal(&snapshot).as_slice(), |_| Ok(())).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let error = ValidationError {
+        path: b"a".to_vec(),
+        message: "internal node of capacity 256 has only 2 children".to_string(),
+    };
+    assert_eq!(
+        error.to_string(),
+        "root -> 0x61: internal node of capacity 256 has only 2 children"
+    );
+}
+
+/// Returns a snapshot of `nodes` with the header and the checksum.
+fn seal(nodes: &[u8]) -> Vec<u8> {
+    let mut snapshot = b"ART\0\x01\0\0\0".to_vec();
+    snapshot.extend_from_slice(nodes);
+    let checksum = snapshot.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    snapshot.extend_from_slice(&checksum.to_le_bytes());
+    snapshot
+}
+
+#[test]
+fn snapshot_invalid() {
+    // A node of capacity 256 with a leaf of `leaf` as its header.
+    let root = |leaf: &[u8]| {
+        let mut nodes = b"\x03\0\x01\0".to_vec();
+        nodes.push(leaf[0]);
+        nodes.extend_from_slice(&[4, leaf.len() as u8]);
+        nodes.extend_from_slice(leaf);
+        nodes.extend_from_slice(&[0; 8]);
+        seal(&nodes)
+    };
+    let art = Art::<(), [u8]>::read_snapshot(&mut root(b"a\xfe\x01\xff").as_slice(), |_| Ok(()));
+    assert_eq!(
+        art.unwrap().keys().collect::<Vec<_>>(),
+        vec![b"a\xff".to_vec()]
+    );
+    for leaf in &[&b"a\xfe\xff"[..], b"a\xfe\x02\xff"] {
+        let art = Art::<(), [u8]>::read_snapshot(&mut root(leaf).as_slice(), |_| Ok(()));
+        assert_eq!(art.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    let art = Art::<(), [u8]>::read_snapshot(&mut root(b"a\x80\xff").as_slice(), |_| Ok(()));
+    assert_eq!(
+        art.unwrap().keys().collect::<Vec<_>>(),
+        vec![b"a\x80".to_vec()]
+    );
+    let art = Art::<()>::read_snapshot(&mut root(b"a\x80\xff").as_slice(), |_| Ok(()));
+    assert_eq!(art.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    let art = Art::<(), u16>::read_snapshot(&mut root(b"a\xff").as_slice(), |_| Ok(()));
+    assert_eq!(art.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut bytes = vec![];
+    ("a\0".to_string(), 7u32).encode(&mut bytes);
+    assert!(<(String, u32)>::is_encoding(&bytes));
+    assert!(!<(String, u32)>::is_encoding(&bytes[..bytes.len() - 1]));
+    assert!(!<(String, u32)>::is_encoding(&bytes[..3]));
+
+    // A chain of internal nodes deeper than the 2 MiB stack of a test thread can hold in recursion.
+    let mut nodes = b"\x03\0\x01\0a".to_vec();
+    for _ in 0..200_000 {
+        nodes.extend_from_slice(b"\0\x01a\x01\0a");
+    }
+    let mut snapshot = seal(&nodes);
+    snapshot.truncate(snapshot.len() - 8);
+    let error = Art::<()>::read_snapshot(&mut snapshot.as_slice(), |_| Ok(())).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn dump() {
+    let mut art = Art::new();
+    for (i, key) in ["ab", "ac", "x\"\\", "a"].iter().enumerate() {
+        assert!(art.insert(key, i).is_ok());
+    }
+
+    assert_eq!(
+        art.dump(),
+        "Node256 \"\" (4 leaves)\n\
+         +-- [a] Node4 \"a\" (3 leaves)\n\
+         |   +-- [b] Leaf \"b\\xff\" = 0\n\
+         |   +-- [c] Leaf \"c\\xff\" = 1\n\
+         |   `-- [\\xff] Leaf \"\\xff\" = 3\n\
+         `-- [x] Leaf \"x\"\\x5c\\xff\" = 2\n"
+    );
+
+    let dot = art.to_dot();
+    assert!(dot.starts_with("digraph art {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("n0 -> n1 [label=\"a\"];"));
+    assert!(dot.contains("[shape=ellipse, label=\"Leaf \\\"x\\\"\\\\x5c\\\\xff\\\" = 2\"];"));
+    assert_eq!(dot.matches("->").count(), 5);
+}
+
+/// Runs random operations on `map` in threads with disjoint keys sharing prefixes, and checks the
+/// results of each thread.
+fn concurrent_stress<M: ConcurrentMap<usize> + Sync>(map: &M) {
+    const THREADS: usize = 8;
+    const OPS: usize = 1 << 14;
+
+    let hashmaps = crossbeam_utils::thread::scope(|s| {
+        let handles = (0..THREADS)
+            .map(|t| {
+                s.spawn(move |_| {
+                    let mut rng = thread_rng();
+                    let mut hashmap = HashMap::<String, usize>::new();
+                    for _ in 0..OPS {
+                        let key = format!(
+                            "{}{}{}",
+                            "P".repeat(rng.gen_range(0, 3) * 10),
+                            (0..rng.gen_range(0, 4))
+                                .map(|_| *['a', 'b', 'c'].choose(&mut rng).unwrap())
+                                .collect::<String>(),
+                            t
+                        );
+                        let value = rng.gen::<usize>() / 2;
+                        let guard = pin();
+                        match rng.gen_range(0, 6) {
+                            0 => {
+                                let expected = if hashmap.contains_key(&key) {
+                                    Err(value)
+                                } else {
+                                    Ok(())
+                                };
+                                assert_eq!(map.insert(&key, value, &guard), expected);
+                                let _ = hashmap.entry(key).or_insert(value);
+                            }
+                            1 => {
+                                assert_eq!(map.delete(&key, &guard), hashmap.remove(&key).ok_or(()))
+                            }
+                            2 => assert_eq!(
+                                map.insert_or_replace(&key, value, &guard),
+                                hashmap.insert(key, value)
+                            ),
+                            3 => {
+                                map.upsert(&key, |v| v.map_or(0, |v| v + 1), &guard);
+                                let v = hashmap.entry(key).or_insert(usize::MAX);
+                                *v = v.wrapping_add(1);
+                            }
+                            4 => map.lookup(&key, &guard, |v| assert_eq!(v, hashmap.get(&key))),
+                            _ => {
+                                let other = format!(
+                                    "{}{}",
+                                    &key[..key.len() - 1],
+                                    rng.gen_range(0, THREADS)
+                                );
+                                map.lookup(&other, &guard, |_| ());
+                            }
+                        }
+                    }
+                    hashmap
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    })
+    .unwrap();
+
+    let guard = pin();
+    for (key, value) in hashmaps.iter().flatten() {
+        map.lookup(key, &guard, |v| assert_eq!(v, Some(value)));
+    }
+    for (key, value) in hashmaps.iter().flatten() {
+        assert_eq!(map.delete(key, &guard), Ok(*value));
+    }
+    map.lookup("PPPPPPPPPPa0", &guard, |v| assert_eq!(v, None));
+}
+
+#[test]
+fn olc_art() {
+    let map = OlcArt::new();
+    let guard = pin();
+    assert_eq!(map.insert("a", 1, &guard), Ok(()));
+    assert_eq!(map.insert("a", 2, &guard), Err(2));
+    assert_eq!(map.insert_or_replace("ab", 3, &guard), None);
+    map.lookup("a", &guard, |v| assert_eq!(v, Some(&1)));
+    assert_eq!(map.delete("a", &guard), Ok(1));
+    assert_eq!(map.delete("a", &guard), Err(()));
+    map.lookup("ab", &guard, |v| assert_eq!(v, Some(&3)));
+    drop(guard);
+
+    concurrent_stress(&map);
+}
+
+#[test]
+fn rowex_art() {
+    let map = RowexArt::<usize>::new();
+    let guard = pin();
+    assert_eq!(map.insert("a", 1, &guard), Ok(()));
+    assert_eq!(map.insert("a", 2, &guard), Err(2));
+    assert_eq!(map.insert_or_replace("ab", 3, &guard), None);
+    map.lookup("a", &guard, |v| assert_eq!(v, Some(&1)));
+    assert_eq!(map.delete("a", &guard), Ok(1));
+    assert_eq!(map.delete("a", &guard), Err(()));
+    map.lookup("ab", &guard, |v| assert_eq!(v, Some(&3)));
+    drop(guard);
+
+    concurrent_stress(&map);
+}
+
+#[test]
+fn plc_art() {
+    let map = PlcArt::<usize>::new();
+    let guard = pin();
+    assert_eq!(map.insert("a", 1, &guard), Ok(()));
+    assert_eq!(map.insert("a", 2, &guard), Err(2));
+    assert_eq!(map.insert_or_replace("ab", 3, &guard), None);
+    map.lookup("a", &guard, |v| assert_eq!(v, Some(&1)));
+    assert_eq!(map.delete("a", &guard), Ok(1));
+    assert_eq!(map.delete("a", &guard), Err(()));
+    map.lookup("ab", &guard, |v| assert_eq!(v, Some(&3)));
+    drop(guard);
+
+    concurrent_stress(&map);
+    concurrent_stress(&PlcArt::<usize, TicketLock>::new());
+}
+
+#[test]
+fn rwlock() {
+    let map = RwLock::<SpinRwLock, Art<usize>>::new(Art::new());
+    let guard = pin();
+    assert_eq!(map.insert("a", 1, &guard), Ok(()));
+    assert_eq!(map.insert_or_replace("b", 2, &guard), None);
+    map.upsert("a", |v| v.unwrap() + 1, &guard);
+    {
+        let first = map.read();
+        let second = map.read();
+        assert_eq!(first.lookup("a"), Some(&2));
+        assert_eq!(
+            second.iter().collect::<Vec<_>>(),
+            [("a".to_string(), &2), ("b".to_string(), &2)]
+        );
+    }
+    assert_eq!(map.delete("b", &guard), Ok(2));
+    drop(guard);
+
+    concurrent_stress(&map);
+    assert_eq!(map.into_inner().len(), 1);
+}
+
+#[test]
+fn left_right() {
+    let map = LeftRight::<SpinLock, Art<usize>>::default();
+    let guard = pin();
+    assert_eq!(map.insert("a", 1, &guard), Ok(()));
+    assert_eq!(map.insert("a", 2, &guard), Err(2));
+    assert_eq!(map.insert_or_replace("b", 2, &guard), None);
+    map.upsert("a", |v| v.unwrap() + 1, &guard);
+    map.lookup("a", &guard, |v| assert_eq!(v, Some(&2)));
+    assert_eq!(map.read(|art| art.len()), 2);
+    assert_eq!(map.delete("b", &guard), Ok(2));
+    assert_eq!(map.delete("b", &guard), Err(()));
+
+    // A panicking reader does not block the writers.
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| map.read(|_| panic!()))).is_err());
+    assert_eq!(map.insert("b", 3, &guard), Ok(()));
+    assert_eq!(map.delete("b", &guard), Ok(3));
+    drop(guard);
+
+    concurrent_stress(&map);
+    assert_eq!(map.into_inner().len(), 1);
+}
+
+#[test]
+fn sharded_art() {
+    let mut rng = thread_rng();
+    for partition in &[Partition::Prefix, Partition::Hash] {
+        let map = ShardedArt::<usize>::new(7, *partition);
+        let mut btreemap = BTreeMap::new();
+        let guard = pin();
+        for i in 0..10_000 {
+            let key = generate_random_string(&mut rng);
+            assert_eq!(
+                map.insert_or_replace(&key, i, &guard),
+                btreemap.insert(key, i)
+            );
+        }
+        assert_eq!(map.len(), btreemap.len());
+
+        for _ in 0..100 {
+            let mut start = generate_random_string(&mut rng);
+            let mut end = generate_random_string(&mut rng);
+            if start > end {
+                std::mem::swap(&mut start, &mut end);
+            }
+            let expected = btreemap
+                .range::<str, _>((Bound::Excluded(&start[..]), Bound::Included(&end[..])))
+                .map(|(k, v)| (k.clone(), *v))
+                .collect::<Vec<_>>();
+            let mut entries = vec![];
+            let range = (Bound::Excluded(&start[..]), Bound::Included(&end[..]));
+            map.range(range, |k, v| entries.push((k.to_string(), *v)));
+            assert_eq!(entries, expected);
+        }
+        let expected = btreemap.into_iter().collect::<Vec<_>>();
+        let mut entries = vec![];
+        map.range::<std::ops::RangeFull, _>(.., |k, v| entries.push((k.to_string(), *v)));
+        assert_eq!(entries, expected);
+        for (key, _) in expected {
+            assert!(map.delete(&key, &guard).is_ok());
+        }
+        assert!(map.is_empty());
+        drop(guard);
+
+        concurrent_stress(&map);
+    }
+}